@@ -0,0 +1,576 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use log::{debug, trace};
+use solana_clock::Slot;
+use solana_commitment_config::CommitmentLevel;
+use solana_pubkey::Pubkey;
+use tokio::select;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinHandle;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{SlotStatus, SubscribeUpdate};
+
+use crate::yellowstone_extractors::{map_account_update, AccountUpdate};
+use crate::Message;
+
+/// latest account state per pubkey with separate processed, confirmed and finalized views; cheap to clone
+///
+/// feed it with account updates (processed commitment) and slot updates of the same source;
+/// account updates are promoted to the confirmed/finalized view when their slot gets confirmed/finalized
+/// and rolled back when their slot is dead or not on the finalized fork
+///
+/// the state of a pubkey is only replaced by an update with higher (slot, write_version);
+/// closed accounts stay in the cache with zero lamports
+///
+/// note: the write version is assigned by the validator, so do not mix sources backed by different nodes
+#[derive(Clone, Default)]
+pub struct AccountCache {
+    inner: Arc<RwLock<AccountCacheInner>>,
+}
+
+#[derive(Default)]
+struct AccountCacheInner {
+    // by view_index
+    views: [HashMap<Pubkey, Arc<AccountUpdate>>; 3],
+    // updates of slots which are not finalized yet; latest per pubkey and slot
+    pending: BTreeMap<Slot, PendingSlot>,
+    finalized_slot: Option<Slot>,
+    watchers: HashMap<(Pubkey, usize), watch::Sender<Option<Arc<AccountUpdate>>>>,
+}
+
+#[derive(Default)]
+struct PendingSlot {
+    confirmed: bool,
+    // from slot updates; used to tell the finalized fork from abandoned forks
+    parent: Option<Slot>,
+    accounts: HashMap<Pubkey, Arc<AccountUpdate>>,
+}
+
+// bounds the pending slots if no finalized slot updates arrive; oldest slots are dropped first
+const MAX_PENDING_SLOTS: usize = 1024;
+
+const PROCESSED: usize = 0;
+const CONFIRMED: usize = 1;
+const FINALIZED: usize = 2;
+
+const fn view_index(commitment: CommitmentLevel) -> usize {
+    match commitment {
+        CommitmentLevel::Processed => PROCESSED,
+        CommitmentLevel::Confirmed => CONFIRMED,
+        CommitmentLevel::Finalized => FINALIZED,
+    }
+}
+
+impl AccountCache {
+    pub fn new() -> Self {
+        AccountCache::default()
+    }
+
+    /// apply an account or slot update; other updates are ignored
+    pub fn update(&self, update: &SubscribeUpdate) {
+        match &update.update_oneof {
+            Some(UpdateOneof::Account(account_update)) => {
+                let Some(account) = map_account_update(account_update.clone()) else {
+                    return;
+                };
+                self.inner
+                    .write()
+                    .expect("account cache lock poisoned")
+                    .on_account(Arc::new(account));
+            }
+            Some(UpdateOneof::Slot(slot_update)) => {
+                let Ok(status) = SlotStatus::try_from(slot_update.status) else {
+                    return;
+                };
+                self.inner
+                    .write()
+                    .expect("account cache lock poisoned")
+                    .on_slot(slot_update.slot, slot_update.parent, status);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn get(&self, pubkey: &Pubkey, commitment: CommitmentLevel) -> Option<Arc<AccountUpdate>> {
+        let inner = self.inner.read().expect("account cache lock poisoned");
+        inner.views[view_index(commitment)].get(pubkey).cloned()
+    }
+
+    /// snapshot of all accounts of the view in arbitrary order
+    pub fn iter(&self, commitment: CommitmentLevel) -> impl Iterator<Item = Arc<AccountUpdate>> {
+        let inner = self.inner.read().expect("account cache lock poisoned");
+        let accounts: Vec<Arc<AccountUpdate>> = inner.views[view_index(commitment)]
+            .values()
+            .cloned()
+            .collect();
+        accounts.into_iter()
+    }
+
+    pub fn len(&self, commitment: CommitmentLevel) -> usize {
+        let inner = self.inner.read().expect("account cache lock poisoned");
+        inner.views[view_index(commitment)].len()
+    }
+
+    pub fn is_empty(&self, commitment: CommitmentLevel) -> bool {
+        self.len(commitment) == 0
+    }
+
+    /// notified whenever the state of the pubkey changes in the view; starts with the current state
+    pub fn watch(
+        &self,
+        pubkey: Pubkey,
+        commitment: CommitmentLevel,
+    ) -> watch::Receiver<Option<Arc<AccountUpdate>>> {
+        let mut inner = self.inner.write().expect("account cache lock poisoned");
+        let view = view_index(commitment);
+        let current = inner.views[view].get(&pubkey).cloned();
+        inner
+            .watchers
+            .entry((pubkey, view))
+            .or_insert_with(|| watch::channel(current).0)
+            .subscribe()
+    }
+}
+
+impl AccountCacheInner {
+    fn on_account(&mut self, account: Arc<AccountUpdate>) {
+        trace!(
+            "account update {} at ({}, {})",
+            account.pubkey,
+            account.slot,
+            account.write_version
+        );
+        self.apply(PROCESSED, &account);
+
+        // snapshot accounts sent on startup are rooted
+        if account.is_startup {
+            self.apply(CONFIRMED, &account);
+            self.apply(FINALIZED, &account);
+            return;
+        }
+        if self
+            .finalized_slot
+            .is_some_and(|finalized_slot| account.slot <= finalized_slot)
+        {
+            // slot is either finalized or abandoned
+            return;
+        }
+
+        let pending = self.pending.entry(account.slot).or_default();
+        let confirmed = pending.confirmed;
+        match pending.accounts.get(&account.pubkey) {
+            Some(existing) if existing.write_version >= account.write_version => {}
+            _ => {
+                pending.accounts.insert(account.pubkey, account.clone());
+            }
+        }
+        // account update arrived after the slot was confirmed
+        if confirmed {
+            self.apply(CONFIRMED, &account);
+        }
+        self.cap_pending();
+    }
+
+    fn on_slot(&mut self, slot: Slot, parent: Option<Slot>, status: SlotStatus) {
+        let above_finalized = self
+            .finalized_slot
+            .is_none_or(|finalized_slot| slot > finalized_slot);
+        if let Some(parent) = parent.filter(|_| above_finalized) {
+            self.pending.entry(slot).or_default().parent = Some(parent);
+        }
+        match status {
+            SlotStatus::SlotConfirmed => {
+                if self
+                    .finalized_slot
+                    .is_some_and(|finalized_slot| slot <= finalized_slot)
+                {
+                    return;
+                }
+                let pending = self.pending.entry(slot).or_default();
+                pending.confirmed = true;
+                let accounts: Vec<Arc<AccountUpdate>> =
+                    pending.accounts.values().cloned().collect();
+                for account in &accounts {
+                    self.apply(CONFIRMED, account);
+                }
+                self.cap_pending();
+            }
+            SlotStatus::SlotFinalized => {
+                if self
+                    .finalized_slot
+                    .is_some_and(|finalized_slot| slot <= finalized_slot)
+                {
+                    return;
+                }
+                self.finalized_slot = Some(slot);
+                let finalized = self.pending.remove(&slot).unwrap_or_default();
+                for account in finalized.accounts.values() {
+                    self.apply(CONFIRMED, account);
+                    self.apply(FINALIZED, account);
+                }
+                self.prune_pending(slot, finalized.parent);
+            }
+            SlotStatus::SlotDead => {
+                if let Some(pending) = self.pending.remove(&slot) {
+                    debug!(
+                        "drop {} pending account updates of dead slot {}",
+                        pending.accounts.len(),
+                        slot
+                    );
+                    self.roll_back(BTreeMap::from([(slot, pending)]));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // slots older than the finalized slot are either on the finalized fork or abandoned;
+    // finalized status updates of ancestors may have been lost (e.g. on reconnect)
+    fn prune_pending(&mut self, finalized_slot: Slot, parent: Option<Slot>) {
+        let retained = self.pending.split_off(&finalized_slot.saturating_add(1));
+        let older = std::mem::replace(&mut self.pending, retained);
+
+        let mut ancestors = HashSet::new();
+        let mut child = finalized_slot;
+        let mut next = parent;
+        // parent links from a bad source must not loop
+        while let Some(ancestor) = next.filter(|ancestor| *ancestor < child) {
+            ancestors.insert(ancestor);
+            next = older.get(&ancestor).and_then(|pending| pending.parent);
+            child = ancestor;
+        }
+        // a confirmed slot is on the finalized fork even if the parent links are incomplete
+        let (on_fork, abandoned): (BTreeMap<_, _>, BTreeMap<_, _>) = older
+            .into_iter()
+            .partition(|(slot, pending)| pending.confirmed || ancestors.contains(slot));
+
+        for pending in on_fork.values() {
+            for account in pending.accounts.values() {
+                self.apply(CONFIRMED, account);
+                self.apply(FINALIZED, account);
+            }
+        }
+        if !abandoned.is_empty() {
+            debug!(
+                "drop pending account updates of {} abandoned slots before {}",
+                abandoned.len(),
+                finalized_slot
+            );
+            self.roll_back(abandoned);
+        }
+    }
+
+    fn cap_pending(&mut self) {
+        let mut dropped = BTreeMap::new();
+        while self.pending.len() > MAX_PENDING_SLOTS {
+            let (slot, pending) = self.pending.pop_first().expect("pending is not empty");
+            dropped.insert(slot, pending);
+        }
+        if !dropped.is_empty() {
+            debug!(
+                "drop pending account updates of {} slots which did not get finalized",
+                dropped.len()
+            );
+            self.roll_back(dropped);
+        }
+    }
+
+    // the processed and confirmed views must not keep the state of dropped slots
+    fn roll_back(&mut self, dropped: BTreeMap<Slot, PendingSlot>) {
+        let pubkeys: HashSet<Pubkey> = dropped
+            .values()
+            .flat_map(|pending| pending.accounts.keys().copied())
+            .collect();
+        // processed falls back to confirmed, so roll back confirmed first
+        for view in [CONFIRMED, PROCESSED] {
+            for pubkey in &pubkeys {
+                let is_dropped = self.views[view]
+                    .get(pubkey)
+                    .is_some_and(|account| dropped.contains_key(&account.slot));
+                if is_dropped {
+                    let state = self.best_state(view, pubkey);
+                    self.replace(view, pubkey, state);
+                }
+            }
+        }
+    }
+
+    // latest state of the pubkey from the pending slots (confirmed ones only for the confirmed view) and the next view
+    fn best_state(&self, view: usize, pubkey: &Pubkey) -> Option<Arc<AccountUpdate>> {
+        self.pending
+            .values()
+            .filter(|pending| view == PROCESSED || pending.confirmed)
+            .filter_map(|pending| pending.accounts.get(pubkey))
+            .chain(self.views[view + 1].get(pubkey))
+            .max_by_key(|account| (account.slot, account.write_version))
+            .cloned()
+    }
+
+    fn replace(&mut self, view: usize, pubkey: &Pubkey, state: Option<Arc<AccountUpdate>>) {
+        trace!(
+            "roll back {} in view {} to slot {:?}",
+            pubkey,
+            view,
+            state.as_ref().map(|account| account.slot)
+        );
+        match &state {
+            Some(account) => self.views[view].insert(*pubkey, account.clone()),
+            None => self.views[view].remove(pubkey),
+        };
+        self.notify(view, pubkey, state);
+    }
+
+    fn apply(&mut self, view: usize, account: &Arc<AccountUpdate>) {
+        let accounts = &mut self.views[view];
+        if let Some(existing) = accounts.get(&account.pubkey) {
+            if (existing.slot, existing.write_version) >= (account.slot, account.write_version) {
+                return;
+            }
+        }
+        accounts.insert(account.pubkey, account.clone());
+        self.notify(view, &account.pubkey, Some(account.clone()));
+    }
+
+    fn notify(&mut self, view: usize, pubkey: &Pubkey, state: Option<Arc<AccountUpdate>>) {
+        if let Some(watcher) = self.watchers.get(&(*pubkey, view)) {
+            if watcher.send(state).is_err() {
+                // all receivers dropped
+                self.watchers.remove(&(*pubkey, view));
+            }
+        }
+    }
+}
+
+/// feed the account cache from an autoconnect task; ends on exit signal or when the upstream is closed
+pub fn spawn_account_cache_updater(
+    account_cache: AccountCache,
+    mut upstream: mpsc::Receiver<Message>,
+    mut exit_notify: broadcast::Receiver<()>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            select! {
+                message = upstream.recv() => {
+                    match message {
+                        Some(Message::GeyserSubscribeUpdate(update)) => {
+                            account_cache.update(&update);
+                        }
+                        // slot updates may be lost on reconnect; pending slots are bounded by MAX_PENDING_SLOTS
+                        Some(Message::Connecting(_)) => {}
+                        None => {
+                            debug!("upstream closed - stop account cache updater");
+                            break;
+                        }
+                    }
+                }
+                _ = exit_notify.recv() => {
+                    break;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_account_update, mock_slot_status_update};
+
+    fn account_update(
+        pubkey: Pubkey,
+        slot: Slot,
+        write_version: u64,
+        lamports: u64,
+    ) -> SubscribeUpdate {
+        let owner = Pubkey::new_from_array([9; 32]);
+        mock_account_update(pubkey, owner, slot, write_version, lamports, vec![])
+    }
+
+    fn slot_update(slot: Slot, parent: Slot, status: SlotStatus) -> SubscribeUpdate {
+        let mut update = mock_slot_status_update(slot, status);
+        if let Some(UpdateOneof::Slot(slot_update)) = &mut update.update_oneof {
+            slot_update.parent = Some(parent);
+        }
+        update
+    }
+
+    fn lamports(cache: &AccountCache, pubkey: &Pubkey, commitment: CommitmentLevel) -> Option<u64> {
+        cache
+            .get(pubkey, commitment)
+            .map(|account| account.lamports)
+    }
+
+    #[test]
+    fn test_commitment_views() {
+        let cache = AccountCache::new();
+        let pubkey_a = Pubkey::new_from_array([1; 32]);
+        let pubkey_b = Pubkey::new_from_array([2; 32]);
+
+        cache.update(&account_update(pubkey_a, 100, 2, 1000));
+        // older write version is ignored
+        cache.update(&account_update(pubkey_a, 100, 1, 999));
+        // fork: slot 101 will be abandoned
+        cache.update(&account_update(pubkey_a, 101, 3, 2000));
+        cache.update(&account_update(pubkey_b, 102, 4, 3000));
+        assert_eq!(
+            lamports(&cache, &pubkey_a, CommitmentLevel::Processed),
+            Some(2000)
+        );
+        assert_eq!(
+            lamports(&cache, &pubkey_a, CommitmentLevel::Confirmed),
+            None
+        );
+
+        cache.update(&mock_slot_status_update(100, SlotStatus::SlotConfirmed));
+        assert_eq!(
+            lamports(&cache, &pubkey_a, CommitmentLevel::Confirmed),
+            Some(1000)
+        );
+        assert_eq!(
+            lamports(&cache, &pubkey_a, CommitmentLevel::Finalized),
+            None
+        );
+
+        cache.update(&mock_slot_status_update(100, SlotStatus::SlotFinalized));
+        cache.update(&mock_slot_status_update(102, SlotStatus::SlotConfirmed));
+        cache.update(&mock_slot_status_update(102, SlotStatus::SlotFinalized));
+        assert_eq!(
+            lamports(&cache, &pubkey_a, CommitmentLevel::Finalized),
+            Some(1000)
+        );
+        assert_eq!(
+            lamports(&cache, &pubkey_b, CommitmentLevel::Finalized),
+            Some(3000)
+        );
+        assert_eq!(cache.len(CommitmentLevel::Finalized), 2);
+        assert_eq!(cache.len(CommitmentLevel::Processed), 2);
+        // processed view rolled back from the abandoned slot 101
+        assert_eq!(
+            lamports(&cache, &pubkey_a, CommitmentLevel::Processed),
+            Some(1000)
+        );
+
+        // dead slot
+        cache.update(&account_update(pubkey_a, 103, 5, 4000));
+        cache.update(&account_update(pubkey_b, 104, 6, 5000));
+        cache.update(&account_update(pubkey_b, 105, 7, 6000));
+        cache.update(&mock_slot_status_update(105, SlotStatus::SlotDead));
+        assert_eq!(
+            lamports(&cache, &pubkey_a, CommitmentLevel::Processed),
+            Some(4000)
+        );
+        assert_eq!(
+            lamports(&cache, &pubkey_b, CommitmentLevel::Processed),
+            Some(5000)
+        );
+
+        // pending updates of the abandoned slot were dropped
+        cache.update(&mock_slot_status_update(101, SlotStatus::SlotConfirmed));
+        assert_eq!(
+            lamports(&cache, &pubkey_a, CommitmentLevel::Confirmed),
+            Some(1000)
+        );
+
+        let mut finalized: Vec<u64> = cache
+            .iter(CommitmentLevel::Finalized)
+            .map(|account| account.lamports)
+            .collect();
+        finalized.sort();
+        assert_eq!(finalized, vec![1000, 3000]);
+    }
+
+    #[test]
+    fn test_lost_finalized_status() {
+        let cache = AccountCache::new();
+        let pubkey_a = Pubkey::new_from_array([1; 32]);
+        let pubkey_b = Pubkey::new_from_array([2; 32]);
+        let pubkey_c = Pubkey::new_from_array([3; 32]);
+
+        // confirmed, but no parent information
+        cache.update(&account_update(pubkey_c, 98, 1, 500));
+        cache.update(&mock_slot_status_update(98, SlotStatus::SlotConfirmed));
+        // ancestor of the finalized slot; confirmed and finalized status got lost
+        cache.update(&account_update(pubkey_a, 100, 2, 1000));
+        cache.update(&slot_update(100, 99, SlotStatus::SlotProcessed));
+        // abandoned fork
+        cache.update(&account_update(pubkey_a, 101, 3, 2000));
+        cache.update(&slot_update(101, 100, SlotStatus::SlotProcessed));
+        cache.update(&account_update(pubkey_b, 102, 4, 3000));
+        cache.update(&slot_update(102, 100, SlotStatus::SlotProcessed));
+        cache.update(&slot_update(102, 100, SlotStatus::SlotFinalized));
+
+        for commitment in [
+            CommitmentLevel::Processed,
+            CommitmentLevel::Confirmed,
+            CommitmentLevel::Finalized,
+        ] {
+            assert_eq!(lamports(&cache, &pubkey_a, commitment), Some(1000));
+            assert_eq!(lamports(&cache, &pubkey_b, commitment), Some(3000));
+            assert_eq!(lamports(&cache, &pubkey_c, commitment), Some(500));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let cache = AccountCache::new();
+        let pubkey = Pubkey::new_from_array([1; 32]);
+        let (exit_tx, exit_rx) = broadcast::channel(1);
+        let (upstream_tx, upstream_rx) = mpsc::channel(16);
+        let jh_updater = spawn_account_cache_updater(cache.clone(), upstream_rx, exit_rx);
+
+        let mut processed_rx = cache.watch(pubkey, CommitmentLevel::Processed);
+        let mut confirmed_rx = cache.watch(pubkey, CommitmentLevel::Confirmed);
+        assert!(processed_rx.borrow_and_update().is_none());
+
+        for update in [
+            account_update(pubkey, 200, 1, 500),
+            mock_slot_status_update(200, SlotStatus::SlotConfirmed),
+        ] {
+            upstream_tx
+                .send(Message::GeyserSubscribeUpdate(Box::new(update)))
+                .await
+                .unwrap();
+        }
+
+        processed_rx.changed().await.unwrap();
+        assert_eq!(
+            processed_rx.borrow_and_update().as_ref().unwrap().lamports,
+            500
+        );
+        confirmed_rx.changed().await.unwrap();
+        assert_eq!(confirmed_rx.borrow_and_update().as_ref().unwrap().slot, 200);
+
+        exit_tx.send(()).unwrap();
+        jh_updater.await.unwrap();
+    }
+
+    #[test]
+    fn test_cap_pending() {
+        let cache = AccountCache::new();
+        let pubkey = Pubkey::new_from_array([1; 32]);
+        cache.update(&account_update(pubkey, 1, 1, 100));
+        cache.update(&mock_slot_status_update(1, SlotStatus::SlotFinalized));
+        for slot in 2..=MAX_PENDING_SLOTS as u64 + 2 {
+            cache.update(&account_update(pubkey, slot, slot, slot * 100));
+        }
+        let inner = cache.inner.read().unwrap();
+        assert_eq!(inner.pending.len(), MAX_PENDING_SLOTS);
+        assert_eq!(inner.pending.keys().next(), Some(&3));
+        drop(inner);
+        assert_eq!(
+            lamports(&cache, &pubkey, CommitmentLevel::Processed),
+            Some((MAX_PENDING_SLOTS as u64 + 2) * 100)
+        );
+
+        // bogus finalized slot must not overflow
+        cache.update(&mock_slot_status_update(
+            u64::MAX,
+            SlotStatus::SlotFinalized,
+        ));
+        assert_eq!(
+            lamports(&cache, &pubkey, CommitmentLevel::Processed),
+            Some(100)
+        );
+    }
+}
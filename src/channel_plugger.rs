@@ -1,4 +1,11 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
 use log::debug;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, Notify};
+use tokio::task::JoinHandle;
 
 /// usage: see plug_pattern test
 pub fn spawn_broadcast_channel_plug<T: Send + 'static>(
@@ -12,11 +19,10 @@ pub fn spawn_broadcast_channel_plug<T: Send + 'static>(
     downstream_broadcast.1
 }
 
-/// note: backpressure will NOT get propagated to upstream
+/// note: backpressure will NOT get propagated to upstream; see ``spawn_fanout_hub`` for per-subscriber policies
 pub fn spawn_plugger_mpcs_to_broadcast<T: Send + 'static>(
     mut upstream: tokio::sync::mpsc::Receiver<T>,
     downstream: tokio::sync::broadcast::Sender<T>,
-    // TODO allow multiple downstreams + fanout
 ) {
     // abort forwarder by closing the sender
     let _private_handler = tokio::spawn(async move {
@@ -35,6 +41,338 @@ pub fn spawn_plugger_mpcs_to_broadcast<T: Send + 'static>(
     });
 }
 
+pub type SubscriberId = u64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubscriberPolicy {
+    // bounded mpsc; a full subscriber blocks the hub, i.e. backpressure is propagated to upstream and all other subscribers
+    Blocking { capacity: usize },
+    // bounded queue; if the subscriber is full the oldest message gets dropped
+    DropOldest { capacity: usize },
+    // broadcast; a slow subscriber lags and misses messages
+    Lossy { capacity: usize },
+}
+
+pub struct SubscriberStats {
+    pub id: SubscriberId,
+    pub policy: SubscriberPolicy,
+    pub delivered: u64,
+    // messages the subscriber missed because it was too slow
+    pub dropped: u64,
+    pub queued: usize,
+}
+
+#[derive(Default)]
+struct SubscriberCounters {
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+}
+
+struct DropOldestQueue<T> {
+    queue: Mutex<VecDeque<T>>,
+    capacity: usize,
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+impl<T> DropOldestQueue<T> {
+    /// returns true if the oldest message was dropped
+    fn push(&self, value: T) -> bool {
+        let mut queue = self.queue.lock().expect("queue lock poisoned");
+        let dropped = if queue.len() >= self.capacity {
+            queue.pop_front();
+            true
+        } else {
+            false
+        };
+        queue.push_back(value);
+        drop(queue);
+        self.notify.notify_one();
+        dropped
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+}
+
+enum SubscriberSender<T> {
+    Blocking(mpsc::Sender<T>),
+    DropOldest(Arc<DropOldestQueue<T>>),
+    Lossy(broadcast::Sender<T>),
+}
+
+enum SubscriberReceiver<T> {
+    Blocking(mpsc::Receiver<T>),
+    DropOldest(Arc<DropOldestQueue<T>>),
+    Lossy(broadcast::Receiver<T>),
+}
+
+type SubscriberFilter<T> = Box<dyn Fn(&T) -> bool + Send>;
+
+struct Subscriber<T> {
+    id: SubscriberId,
+    policy: SubscriberPolicy,
+    sender: SubscriberSender<T>,
+    // messages which do not pass the filter are not counted as dropped
+    filter: Option<SubscriberFilter<T>>,
+    counters: Arc<SubscriberCounters>,
+}
+
+impl<T> Subscriber<T> {
+    fn is_detached(&self) -> bool {
+        match &self.sender {
+            SubscriberSender::Blocking(tx) => tx.is_closed(),
+            SubscriberSender::DropOldest(queue) => Arc::strong_count(queue) == 1,
+            SubscriberSender::Lossy(tx) => tx.receiver_count() == 0,
+        }
+    }
+
+    fn queued(&self) -> usize {
+        match &self.sender {
+            SubscriberSender::Blocking(tx) => tx.max_capacity() - tx.capacity(),
+            SubscriberSender::DropOldest(queue) => {
+                queue.queue.lock().expect("queue lock poisoned").len()
+            }
+            SubscriberSender::Lossy(tx) => tx.len(),
+        }
+    }
+
+    fn close(self) {
+        if let SubscriberSender::DropOldest(queue) = &self.sender {
+            queue.close();
+        }
+    }
+}
+
+/// receiving end of a fanout subscription; returns None after detach or hub shutdown
+pub struct FanoutReceiver<T> {
+    receiver: SubscriberReceiver<T>,
+    counters: Arc<SubscriberCounters>,
+}
+
+impl<T: Clone> FanoutReceiver<T> {
+    pub async fn recv(&mut self) -> Option<T> {
+        let value = match &mut self.receiver {
+            SubscriberReceiver::Blocking(rx) => rx.recv().await,
+            SubscriberReceiver::DropOldest(queue) => loop {
+                let next = queue.queue.lock().expect("queue lock poisoned").pop_front();
+                if next.is_some() {
+                    break next;
+                }
+                if queue.closed.load(Ordering::Relaxed) {
+                    break None;
+                }
+                queue.notify.notified().await;
+            },
+            SubscriberReceiver::Lossy(rx) => loop {
+                match rx.recv().await {
+                    Ok(value) => break Some(value),
+                    Err(RecvError::Lagged(n_missed)) => {
+                        self.counters.dropped.fetch_add(n_missed, Ordering::Relaxed);
+                    }
+                    Err(RecvError::Closed) => break None,
+                }
+            },
+        };
+        if value.is_some() {
+            self.counters.delivered.fetch_add(1, Ordering::Relaxed);
+        }
+        value
+    }
+}
+
+struct FanoutState<T> {
+    subscribers: Vec<Subscriber<T>>,
+    next_id: SubscriberId,
+    shut_down: bool,
+}
+
+/// handle to attach/detach subscribers of a hub created by ``spawn_fanout_hub``; cheap to clone
+#[derive(Clone)]
+pub struct FanoutHub<T> {
+    state: Arc<Mutex<FanoutState<T>>>,
+}
+
+impl<T: Clone + Send + 'static> FanoutHub<T> {
+    /// subscriber receives all messages which arrive after attaching
+    pub fn attach(&self, policy: SubscriberPolicy) -> (SubscriberId, FanoutReceiver<T>) {
+        self.attach_subscriber(policy, None)
+    }
+
+    /// subscriber receives only the messages which pass the filter
+    pub fn attach_with_filter(
+        &self,
+        policy: SubscriberPolicy,
+        filter: impl Fn(&T) -> bool + Send + 'static,
+    ) -> (SubscriberId, FanoutReceiver<T>) {
+        self.attach_subscriber(policy, Some(Box::new(filter)))
+    }
+
+    fn attach_subscriber(
+        &self,
+        policy: SubscriberPolicy,
+        filter: Option<SubscriberFilter<T>>,
+    ) -> (SubscriberId, FanoutReceiver<T>) {
+        let counters = Arc::new(SubscriberCounters::default());
+        let (sender, receiver) = match policy {
+            SubscriberPolicy::Blocking { capacity } => {
+                let (tx, rx) = mpsc::channel(capacity);
+                (
+                    SubscriberSender::Blocking(tx),
+                    SubscriberReceiver::Blocking(rx),
+                )
+            }
+            SubscriberPolicy::DropOldest { capacity } => {
+                assert!(capacity > 0, "capacity must be positive");
+                let queue = Arc::new(DropOldestQueue {
+                    queue: Mutex::new(VecDeque::with_capacity(capacity)),
+                    capacity,
+                    notify: Notify::new(),
+                    closed: AtomicBool::new(false),
+                });
+                (
+                    SubscriberSender::DropOldest(queue.clone()),
+                    SubscriberReceiver::DropOldest(queue),
+                )
+            }
+            SubscriberPolicy::Lossy { capacity } => {
+                let (tx, rx) = broadcast::channel(capacity);
+                (SubscriberSender::Lossy(tx), SubscriberReceiver::Lossy(rx))
+            }
+        };
+
+        let mut state = self.state.lock().expect("hub lock poisoned");
+        let id = state.next_id;
+        state.next_id += 1;
+        let subscriber = Subscriber {
+            id,
+            policy,
+            sender,
+            filter,
+            counters: counters.clone(),
+        };
+        if state.shut_down {
+            // receiver yields None right away
+            subscriber.close();
+        } else {
+            debug!("attach subscriber {} with policy {:?}", id, policy);
+            state.subscribers.push(subscriber);
+        }
+
+        (id, FanoutReceiver { receiver, counters })
+    }
+
+    /// the receiver gets the messages which are queued already, then None; returns false if the subscriber is unknown
+    pub fn detach(&self, id: SubscriberId) -> bool {
+        let mut state = self.state.lock().expect("hub lock poisoned");
+        let Some(pos) = state.subscribers.iter().position(|s| s.id == id) else {
+            return false;
+        };
+        debug!("detach subscriber {}", id);
+        state.subscribers.remove(pos).close();
+        true
+    }
+
+    pub fn stats(&self) -> Vec<SubscriberStats> {
+        let state = self.state.lock().expect("hub lock poisoned");
+        state
+            .subscribers
+            .iter()
+            .map(|subscriber| SubscriberStats {
+                id: subscriber.id,
+                policy: subscriber.policy,
+                delivered: subscriber.counters.delivered.load(Ordering::Relaxed),
+                dropped: subscriber.counters.dropped.load(Ordering::Relaxed),
+                queued: subscriber.queued(),
+            })
+            .collect()
+    }
+
+    async fn dispatch(&self, value: T) {
+        let mut blocking_senders = vec![];
+        {
+            let mut state = self.state.lock().expect("hub lock poisoned");
+            state.subscribers.retain(|subscriber| {
+                if subscriber.is_detached() {
+                    debug!("subscriber {} was dropped - detach", subscriber.id);
+                    return false;
+                }
+                if let Some(filter) = &subscriber.filter {
+                    if !filter(&value) {
+                        return true;
+                    }
+                }
+                match &subscriber.sender {
+                    SubscriberSender::Blocking(tx) => {
+                        blocking_senders.push(tx.clone());
+                    }
+                    SubscriberSender::DropOldest(queue) => {
+                        if queue.push(value.clone()) {
+                            subscriber.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    SubscriberSender::Lossy(tx) => {
+                        let _ = tx.send(value.clone());
+                    }
+                }
+                true
+            });
+        }
+
+        // lock must not be held while waiting for slow subscribers
+        for tx in blocking_senders {
+            let _ = tx.send(value.clone()).await;
+        }
+    }
+
+    fn shutdown(&self) {
+        let mut state = self.state.lock().expect("hub lock poisoned");
+        state.shut_down = true;
+        for subscriber in state.subscribers.drain(..) {
+            subscriber.close();
+        }
+    }
+}
+
+/// forward each message from upstream to all subscribers attached to the returned hub;
+/// the hub shuts down on exit signal or if upstream is closed - all receivers return None afterwards
+pub fn spawn_fanout_hub<T: Clone + Send + 'static>(
+    mut upstream: mpsc::Receiver<T>,
+    mut exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, FanoutHub<T>) {
+    let hub = FanoutHub {
+        state: Arc::new(Mutex::new(FanoutState {
+            subscribers: vec![],
+            next_id: 0,
+            shut_down: false,
+        })),
+    };
+
+    let hub_task = hub.clone();
+    let jh_hub = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                next = upstream.recv() => {
+                    let Some(value) = next else {
+                        debug!("no more messages from producer - shutting down fanout hub");
+                        break;
+                    };
+                    hub_task.dispatch(value).await;
+                }
+                _ = exit_notify.recv() => {
+                    debug!("exit on signal - shutting down fanout hub");
+                    break;
+                }
+            }
+        }
+        hub_task.shutdown();
+    });
+
+    (jh_hub, hub)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -51,6 +389,71 @@ mod tests {
             spawn_broadcast_channel_plug(tokio::sync::broadcast::channel(8), message_channel);
     }
 
+    #[tokio::test]
+    async fn fanout_policies() {
+        let (tx, rx) = tokio::sync::mpsc::channel::<u32>(16);
+        let (_exit_tx, exit_rx) = tokio::sync::broadcast::channel(1);
+        let (jh_hub, hub) = spawn_fanout_hub(rx, exit_rx);
+
+        let (_, mut blocking) = hub.attach(SubscriberPolicy::Blocking { capacity: 8 });
+        let (_, mut drop_oldest) = hub.attach(SubscriberPolicy::DropOldest { capacity: 2 });
+        let (_, mut lossy) = hub.attach(SubscriberPolicy::Lossy { capacity: 2 });
+        let (detached_id, mut detached) = hub.attach(SubscriberPolicy::Blocking { capacity: 8 });
+        assert!(hub.detach(detached_id));
+        assert!(!hub.detach(detached_id));
+
+        for i in 1..=5 {
+            tx.send(i).await.unwrap();
+        }
+        // blocking subscribers are served last
+        while hub.stats()[0].queued < 5 {
+            sleep(Duration::from_millis(10)).await;
+        }
+        let stats = hub.stats();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[1].dropped, 3);
+
+        drop(tx);
+        jh_hub.await.unwrap();
+
+        let mut received = vec![];
+        while let Some(value) = blocking.recv().await {
+            received.push(value);
+        }
+        assert_eq!(received, vec![1, 2, 3, 4, 5]);
+
+        let mut received = vec![];
+        while let Some(value) = drop_oldest.recv().await {
+            received.push(value);
+        }
+        assert_eq!(received, vec![4, 5]);
+
+        let mut received = vec![];
+        while let Some(value) = lossy.recv().await {
+            received.push(value);
+        }
+        assert_eq!(received, vec![4, 5]);
+        assert_eq!(lossy.counters.dropped.load(Ordering::Relaxed), 3);
+
+        assert_eq!(detached.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn fanout_shutdown_on_exit() {
+        let (_tx, rx) = tokio::sync::mpsc::channel::<u32>(1);
+        let (exit_tx, exit_rx) = tokio::sync::broadcast::channel(1);
+        let (jh_hub, hub) = spawn_fanout_hub(rx, exit_rx);
+        let (_, mut receiver) = hub.attach(SubscriberPolicy::DropOldest { capacity: 1 });
+
+        exit_tx.send(()).unwrap();
+        jh_hub.await.unwrap();
+
+        assert_eq!(receiver.recv().await, None);
+        // attaching after shutdown yields a closed receiver
+        let (_, mut receiver) = hub.attach(SubscriberPolicy::Lossy { capacity: 1 });
+        assert_eq!(receiver.recv().await, None);
+    }
+
     #[tokio::test]
     async fn connect_broadcast_to_mpsc() {
         solana_logger::setup_with_default("debug");
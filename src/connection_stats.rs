@@ -0,0 +1,78 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use yellowstone_grpc_proto::geyser::SubscribeUpdate;
+
+use crate::latency_tracker::LatencyTracker;
+
+/// connection statistics of one autoconnect task; cheap to clone and shared with the task
+#[derive(Clone, Default)]
+pub struct ConnectionStats {
+    inner: Arc<RwLock<ConnectionStatsSnapshot>>,
+    // (source label, tracker); the tracker may be shared by multiple tasks
+    latency_tracker: Option<(String, LatencyTracker)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionStatsSnapshot {
+    // remote IP of the current connection; None if disconnected or the address could not be resolved
+    pub connected_addr: Option<SocketAddr>,
+    pub connected_since: Option<SystemTime>,
+    pub connect_attempts: u64,
+    pub messages_forwarded: u64,
+}
+
+impl ConnectionStats {
+    /// record the latency of all updates received by the task under the given source label
+    pub fn with_latency_tracker(
+        latency_tracker: LatencyTracker,
+        source: impl Into<String>,
+    ) -> Self {
+        ConnectionStats {
+            inner: Arc::default(),
+            latency_tracker: Some((source.into(), latency_tracker)),
+        }
+    }
+
+    pub fn latency_tracker(&self) -> Option<&LatencyTracker> {
+        self.latency_tracker.as_ref().map(|(_, tracker)| tracker)
+    }
+
+    pub fn snapshot(&self) -> ConnectionStatsSnapshot {
+        self.inner.read().expect("stats lock poisoned").clone()
+    }
+
+    pub fn connected_addr(&self) -> Option<SocketAddr> {
+        self.inner
+            .read()
+            .expect("stats lock poisoned")
+            .connected_addr
+    }
+
+    pub(crate) fn on_connect_attempt(&self) {
+        let mut stats = self.inner.write().expect("stats lock poisoned");
+        stats.connected_addr = None;
+        stats.connected_since = None;
+        stats.connect_attempts += 1;
+    }
+
+    pub(crate) fn on_connected(&self, addr: Option<SocketAddr>) {
+        let mut stats = self.inner.write().expect("stats lock poisoned");
+        stats.connected_addr = addr;
+        stats.connected_since = Some(SystemTime::now());
+    }
+
+    pub(crate) fn on_message_forwarded(&self) {
+        self.inner
+            .write()
+            .expect("stats lock poisoned")
+            .messages_forwarded += 1;
+    }
+
+    pub(crate) fn on_update_received(&self, update: &SubscribeUpdate) {
+        if let Some((source, latency_tracker)) = &self.latency_tracker {
+            latency_tracker.record(source, update);
+        }
+    }
+}
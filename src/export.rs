@@ -0,0 +1,682 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use base64::Engine;
+use log::{debug, warn};
+use solana_clock::Slot;
+use solana_pubkey::Pubkey;
+use solana_signature::Signature;
+use yellowstone_grpc_proto::convert_from;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{SlotStatus, SubscribeUpdate, SubscribeUpdateTransactionInfo};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountRecord {
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data_len: u64,
+    pub slot: Slot,
+    pub write_version: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionRecord {
+    pub signature: Signature,
+    pub slot: Slot,
+    pub fee: u64,
+    pub cu_consumed: Option<u64>,
+    // None if the transaction succeeded
+    pub err: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlotRecord {
+    pub slot: Slot,
+    pub parent: Option<Slot>,
+    pub status: SlotStatus,
+}
+
+/// flat view of a geyser update; one file per kind is written by ``UpdateExporter``
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportRecord {
+    Account(AccountRecord),
+    Transaction(TransactionRecord),
+    Slot(SlotRecord),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RecordKind {
+    Account,
+    Transaction,
+    Slot,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataEncoding {
+    Base58,
+    Base64,
+}
+
+enum FieldValue<'a> {
+    U64(u64),
+    Text(String),
+    Bytes(&'a [u8]),
+    Null,
+}
+
+impl RecordKind {
+    /// all columns in default order
+    pub const fn columns(self) -> &'static [&'static str] {
+        match self {
+            RecordKind::Account => &[
+                "pubkey",
+                "owner",
+                "lamports",
+                "data_len",
+                "slot",
+                "write_version",
+                "data",
+            ],
+            RecordKind::Transaction => &["signature", "slot", "fee", "cu_consumed", "err"],
+            RecordKind::Slot => &["slot", "parent", "status"],
+        }
+    }
+
+    const fn file_tag(self) -> &'static str {
+        match self {
+            RecordKind::Account => "accounts",
+            RecordKind::Transaction => "transactions",
+            RecordKind::Slot => "slots",
+        }
+    }
+
+    const fn index(self) -> usize {
+        match self {
+            RecordKind::Account => 0,
+            RecordKind::Transaction => 1,
+            RecordKind::Slot => 2,
+        }
+    }
+}
+
+impl ExportFormat {
+    const fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+impl ExportRecord {
+    pub const fn kind(&self) -> RecordKind {
+        match self {
+            ExportRecord::Account(_) => RecordKind::Account,
+            ExportRecord::Transaction(_) => RecordKind::Transaction,
+            ExportRecord::Slot(_) => RecordKind::Slot,
+        }
+    }
+
+    fn field(&self, column: &str) -> FieldValue<'_> {
+        match (self, column) {
+            (ExportRecord::Account(account), "pubkey") => {
+                FieldValue::Text(account.pubkey.to_string())
+            }
+            (ExportRecord::Account(account), "owner") => {
+                FieldValue::Text(account.owner.to_string())
+            }
+            (ExportRecord::Account(account), "lamports") => FieldValue::U64(account.lamports),
+            (ExportRecord::Account(account), "data_len") => FieldValue::U64(account.data_len),
+            (ExportRecord::Account(account), "slot") => FieldValue::U64(account.slot),
+            (ExportRecord::Account(account), "write_version") => {
+                FieldValue::U64(account.write_version)
+            }
+            (ExportRecord::Account(account), "data") => FieldValue::Bytes(&account.data),
+            (ExportRecord::Transaction(tx), "signature") => {
+                FieldValue::Text(tx.signature.to_string())
+            }
+            (ExportRecord::Transaction(tx), "slot") => FieldValue::U64(tx.slot),
+            (ExportRecord::Transaction(tx), "fee") => FieldValue::U64(tx.fee),
+            (ExportRecord::Transaction(tx), "cu_consumed") => {
+                tx.cu_consumed.map_or(FieldValue::Null, FieldValue::U64)
+            }
+            (ExportRecord::Transaction(tx), "err") => tx
+                .err
+                .as_ref()
+                .map_or(FieldValue::Null, |err| FieldValue::Text(err.clone())),
+            (ExportRecord::Slot(slot), "slot") => FieldValue::U64(slot.slot),
+            (ExportRecord::Slot(slot), "parent") => {
+                slot.parent.map_or(FieldValue::Null, FieldValue::U64)
+            }
+            (ExportRecord::Slot(slot), "status") => {
+                FieldValue::Text(slot.status.as_str_name().to_string())
+            }
+            // columns are validated by the exporter
+            _ => FieldValue::Null,
+        }
+    }
+}
+
+/// flat records of an update; blocks yield one record per transaction; ping, pong, entries and block meta are skipped
+///
+/// accounts and transactions with invalid keys or signatures are skipped
+pub fn flatten_update(update: &SubscribeUpdate) -> Vec<ExportRecord> {
+    match &update.update_oneof {
+        Some(UpdateOneof::Account(account_update)) => account_update
+            .account
+            .as_ref()
+            .and_then(|account| {
+                Some(ExportRecord::Account(AccountRecord {
+                    pubkey: Pubkey::try_from(account.pubkey.as_slice()).ok()?,
+                    owner: Pubkey::try_from(account.owner.as_slice()).ok()?,
+                    lamports: account.lamports,
+                    data_len: account.data.len() as u64,
+                    slot: account_update.slot,
+                    write_version: account.write_version,
+                    data: account.data.clone(),
+                }))
+            })
+            .into_iter()
+            .collect(),
+        Some(UpdateOneof::Transaction(tx_update)) => tx_update
+            .transaction
+            .as_ref()
+            .and_then(|tx| transaction_record(tx_update.slot, tx))
+            .into_iter()
+            .collect(),
+        Some(UpdateOneof::Block(block)) => block
+            .transactions
+            .iter()
+            .filter_map(|tx| transaction_record(block.slot, tx))
+            .collect(),
+        Some(UpdateOneof::Slot(slot_update)) => SlotStatus::try_from(slot_update.status)
+            .ok()
+            .map(|status| {
+                ExportRecord::Slot(SlotRecord {
+                    slot: slot_update.slot,
+                    parent: slot_update.parent,
+                    status,
+                })
+            })
+            .into_iter()
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn transaction_record(slot: Slot, tx: &SubscribeUpdateTransactionInfo) -> Option<ExportRecord> {
+    let signature = Signature::try_from(tx.signature.as_slice()).ok()?;
+    let meta = tx.meta.as_ref()?;
+    let err = match convert_from::create_tx_error(meta.err.as_ref()) {
+        Ok(err) => err.map(|err| err.to_string()),
+        Err(err) => Some(format!("invalid error: {}", err)),
+    };
+    Some(ExportRecord::Transaction(TransactionRecord {
+        signature,
+        slot,
+        fee: meta.fee,
+        cu_consumed: meta.compute_units_consumed,
+        err,
+    }))
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportConfig {
+    pub directory: PathBuf,
+    pub format: ExportFormat,
+    // None: all columns except data
+    pub account_columns: Option<Vec<String>>,
+    pub transaction_columns: Option<Vec<String>>,
+    pub slot_columns: Option<Vec<String>>,
+    // encoding of the account data; required for the data column
+    pub data_encoding: Option<DataEncoding>,
+    // start a new file if the current file of a record kind exceeds this size
+    pub max_file_bytes: u64,
+    // start a new file if the current file of a record kind is older
+    pub max_file_duration: Duration,
+}
+
+impl ExportConfig {
+    pub fn new(directory: impl Into<PathBuf>, format: ExportFormat) -> Self {
+        ExportConfig {
+            directory: directory.into(),
+            format,
+            account_columns: None,
+            transaction_columns: None,
+            slot_columns: None,
+            data_encoding: None,
+            max_file_bytes: 256 * 1024 * 1024,
+            max_file_duration: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// writes flat records into one file per record kind, e.g. `accounts-00000000.csv`; files are rotated by size and age
+///
+/// existing files in the directory are not overwritten
+pub struct UpdateExporter {
+    config: ExportConfig,
+    // by RecordKind::index
+    writers: [KindWriter; 3],
+}
+
+struct KindWriter {
+    kind: RecordKind,
+    columns: Vec<String>,
+    next_file_seq: u64,
+    active: Option<ActiveFile>,
+}
+
+struct ActiveFile {
+    file_name: String,
+    writer: FileWriter,
+    opened_at: SystemTime,
+}
+
+enum FileWriter {
+    Csv(csv::Writer<CountingWriter>),
+    JsonLines(CountingWriter),
+}
+
+struct CountingWriter {
+    inner: BufWriter<File>,
+    bytes_written: u64,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bytes_written += written as u64;
+        Ok(written)
+    }
+
+    // only takes over the internal buffer of the csv writer; see ``CountingWriter::flush_file``
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl CountingWriter {
+    fn flush_file(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl FileWriter {
+    fn bytes_written(&mut self) -> io::Result<u64> {
+        match self {
+            FileWriter::Csv(writer) => {
+                // csv buffers internally
+                writer.flush()?;
+                Ok(writer.get_ref().bytes_written)
+            }
+            FileWriter::JsonLines(writer) => Ok(writer.bytes_written),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FileWriter::Csv(writer) => {
+                writer.flush()?;
+                writer.get_mut().flush_file()
+            }
+            FileWriter::JsonLines(writer) => writer.flush_file(),
+        }
+    }
+}
+
+impl UpdateExporter {
+    /// fails if a configured column does not exist or the data column is selected without data encoding
+    pub fn create(config: ExportConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        let writers = [
+            KindWriter::new(RecordKind::Account, &config.account_columns, &config)?,
+            KindWriter::new(
+                RecordKind::Transaction,
+                &config.transaction_columns,
+                &config,
+            )?,
+            KindWriter::new(RecordKind::Slot, &config.slot_columns, &config)?,
+        ];
+        Ok(UpdateExporter { config, writers })
+    }
+
+    /// returns the number of records written
+    pub fn export(&mut self, update: &SubscribeUpdate) -> io::Result<usize> {
+        let records = flatten_update(update);
+        for record in &records {
+            self.write_record(record)?;
+        }
+        Ok(records.len())
+    }
+
+    pub fn write_record(&mut self, record: &ExportRecord) -> io::Result<()> {
+        self.writers[record.kind().index()].write_record(record, &self.config, SystemTime::now())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        for writer in &mut self.writers {
+            if let Some(active) = &mut writer.active {
+                active.writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UpdateExporter {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            warn!("failed to flush export files: {}", err);
+        }
+    }
+}
+
+impl KindWriter {
+    fn new(
+        kind: RecordKind,
+        columns: &Option<Vec<String>>,
+        config: &ExportConfig,
+    ) -> io::Result<Self> {
+        let columns = match columns {
+            Some(columns) => columns.clone(),
+            None => kind
+                .columns()
+                .iter()
+                .filter(|column| **column != "data" || config.data_encoding.is_some())
+                .map(|column| column.to_string())
+                .collect(),
+        };
+        for column in &columns {
+            if !kind.columns().contains(&column.as_str()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown {} column: {}", kind.file_tag(), column),
+                ));
+            }
+            if column == "data" && config.data_encoding.is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "data column requires a data encoding",
+                ));
+            }
+        }
+        Ok(KindWriter {
+            kind,
+            columns,
+            next_file_seq: 0,
+            active: None,
+        })
+    }
+
+    fn write_record(
+        &mut self,
+        record: &ExportRecord,
+        config: &ExportConfig,
+        now: SystemTime,
+    ) -> io::Result<()> {
+        if let Some(active) = &mut self.active {
+            let file_age = now
+                .duration_since(active.opened_at)
+                .unwrap_or(Duration::ZERO);
+            if active.writer.bytes_written()? >= config.max_file_bytes
+                || file_age >= config.max_file_duration
+            {
+                active.writer.flush()?;
+                debug!("closed export file {}", active.file_name);
+                self.active = None;
+            }
+        }
+        if self.active.is_none() {
+            self.active = Some(self.open_file(config, now)?);
+        }
+        let writer = &mut self.active.as_mut().expect("file was opened").writer;
+
+        let values = self
+            .columns
+            .iter()
+            .map(|column| (column.as_str(), record.field(column)));
+        match writer {
+            FileWriter::Csv(writer) => {
+                let row: Vec<String> = values
+                    .map(|(_, value)| match value {
+                        FieldValue::U64(value) => value.to_string(),
+                        FieldValue::Text(text) => text,
+                        FieldValue::Bytes(bytes) => encode_data(bytes, config.data_encoding),
+                        FieldValue::Null => String::new(),
+                    })
+                    .collect();
+                writer.write_record(&row)?;
+            }
+            FileWriter::JsonLines(writer) => {
+                // keeps the configured column order
+                writer.write_all(b"{")?;
+                for (i, (column, value)) in values.enumerate() {
+                    if i > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    serde_json::to_writer(&mut *writer, column)?;
+                    writer.write_all(b":")?;
+                    match value {
+                        FieldValue::U64(value) => serde_json::to_writer(&mut *writer, &value)?,
+                        FieldValue::Text(text) => serde_json::to_writer(&mut *writer, &text)?,
+                        FieldValue::Bytes(bytes) => serde_json::to_writer(
+                            &mut *writer,
+                            &encode_data(bytes, config.data_encoding),
+                        )?,
+                        FieldValue::Null => writer.write_all(b"null")?,
+                    }
+                }
+                writer.write_all(b"}\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn open_file(&mut self, config: &ExportConfig, now: SystemTime) -> io::Result<ActiveFile> {
+        let (file_name, path, file) = loop {
+            let file_name = format!(
+                "{}-{:08}.{}",
+                self.kind.file_tag(),
+                self.next_file_seq,
+                config.format.extension()
+            );
+            self.next_file_seq += 1;
+            let path = config.directory.join(&file_name);
+            // never truncate a file of another exporter or an earlier run
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => break (file_name, path, file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        };
+        debug!("start export file {}", path.display());
+
+        let file = CountingWriter {
+            inner: BufWriter::new(file),
+            bytes_written: 0,
+        };
+        let writer = match config.format {
+            ExportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(file);
+                writer.write_record(&self.columns)?;
+                FileWriter::Csv(writer)
+            }
+            ExportFormat::JsonLines => FileWriter::JsonLines(file),
+        };
+        Ok(ActiveFile {
+            file_name,
+            writer,
+            opened_at: now,
+        })
+    }
+}
+
+fn encode_data(data: &[u8], data_encoding: Option<DataEncoding>) -> String {
+    match data_encoding {
+        Some(DataEncoding::Base58) => bs58::encode(data).into_string(),
+        Some(DataEncoding::Base64) => base64::engine::general_purpose::STANDARD.encode(data),
+        // rejected on create
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_account_update, mock_slot_status_update};
+    use std::path::Path;
+    use yellowstone_grpc_proto::geyser::SubscribeUpdateTransaction;
+    use yellowstone_grpc_proto::prelude::TransactionStatusMeta;
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("geyser-export-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn account_update(slot: Slot, write_version: u64) -> SubscribeUpdate {
+        mock_account_update(
+            Pubkey::new_from_array([1; 32]),
+            Pubkey::new_from_array([2; 32]),
+            slot,
+            write_version,
+            1_000_000,
+            vec![1, 2, 3],
+        )
+    }
+
+    fn read_files(directory: &Path, prefix: &str) -> Vec<String> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with(prefix)
+            })
+            .collect();
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_csv_columns_and_rotation() {
+        let directory = test_directory("csv");
+        let mut exporter = UpdateExporter::create(ExportConfig {
+            account_columns: Some(vec!["slot".to_string(), "pubkey".to_string()]),
+            max_file_bytes: 1,
+            ..ExportConfig::new(&directory, ExportFormat::Csv)
+        })
+        .unwrap();
+
+        assert_eq!(exporter.export(&account_update(42, 1)).unwrap(), 1);
+        assert_eq!(exporter.export(&account_update(43, 2)).unwrap(), 1);
+        let tx_update = SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+                transaction: Some(SubscribeUpdateTransactionInfo {
+                    signature: vec![3; 64],
+                    meta: Some(TransactionStatusMeta {
+                        fee: 5000,
+                        compute_units_consumed: Some(150),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                slot: 42,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        assert_eq!(exporter.export(&tx_update).unwrap(), 1);
+        drop(exporter);
+
+        let pubkey = Pubkey::new_from_array([1; 32]);
+        assert_eq!(
+            read_files(&directory, "accounts-"),
+            vec![
+                format!("slot,pubkey\n42,{}\n", pubkey),
+                format!("slot,pubkey\n43,{}\n", pubkey),
+            ]
+        );
+        assert_eq!(
+            read_files(&directory, "transactions-"),
+            vec![format!(
+                "signature,slot,fee,cu_consumed,err\n{},42,5000,150,\n",
+                Signature::from([3; 64])
+            )]
+        );
+
+        assert!(UpdateExporter::create(ExportConfig {
+            slot_columns: Some(vec!["lamports".to_string()]),
+            ..ExportConfig::new(&directory, ExportFormat::Csv)
+        })
+        .is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_keep_existing_files() {
+        let directory = test_directory("existing");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("accounts-00000000.jsonl"), "existing\n").unwrap();
+
+        let mut exporter =
+            UpdateExporter::create(ExportConfig::new(&directory, ExportFormat::JsonLines)).unwrap();
+        exporter.export(&account_update(42, 1)).unwrap();
+        drop(exporter);
+
+        let files = read_files(&directory, "accounts-");
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0], "existing\n");
+        assert!(files[1].contains("\"slot\":42"));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_json_lines_with_data() {
+        let directory = test_directory("jsonl");
+        let mut exporter = UpdateExporter::create(ExportConfig {
+            data_encoding: Some(DataEncoding::Base64),
+            ..ExportConfig::new(&directory, ExportFormat::JsonLines)
+        })
+        .unwrap();
+        exporter.export(&account_update(42, 7)).unwrap();
+        exporter
+            .export(&mock_slot_status_update(43, SlotStatus::SlotConfirmed))
+            .unwrap();
+        drop(exporter);
+
+        let account: serde_json::Value =
+            serde_json::from_str(read_files(&directory, "accounts-")[0].trim()).unwrap();
+        assert_eq!(account["data"], "AQID");
+        assert_eq!(account["data_len"], 3);
+        assert_eq!(account["write_version"], 7);
+        assert_eq!(
+            account["owner"],
+            Pubkey::new_from_array([2; 32]).to_string()
+        );
+        assert_eq!(
+            read_files(&directory, "slots-"),
+            vec!["{\"slot\":43,\"parent\":null,\"status\":\"SLOT_CONFIRMED\"}\n".to_string()]
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,427 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_stream::stream;
+use futures::{Stream, StreamExt};
+use log::debug;
+use tokio::select;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep, sleep_until, Instant};
+use yellowstone_grpc_proto::prost;
+use yellowstone_grpc_proto::tonic::Status;
+
+/// faults applied to every message of the wrapped streams; the default config passes all messages unchanged
+#[derive(Clone, Debug, Default)]
+pub struct FaultConfig {
+    // fixed delay added to every message
+    pub latency: Duration,
+    // random extra delay between zero and jitter; message order is preserved
+    pub jitter: Duration,
+    // limit the throughput to this many encoded message bytes per second
+    pub bandwidth_bytes_per_sec: Option<u64>,
+    // probability (0.0..=1.0) to silently drop a message
+    pub drop_probability: f64,
+    // probability to terminate the stream with an error instead of delivering the message
+    pub reset_probability: f64,
+    // probability to stop delivering messages for stall_duration before this message
+    pub stall_probability: f64,
+    pub stall_duration: Duration,
+    // seed for the random decisions; 0 picks a seed from the clock
+    pub seed: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    pub delivered: u64,
+    pub dropped: u64,
+    pub resets: u64,
+    pub stalls: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct FaultControl {
+    stalled: bool,
+    // incremented to reset all wrapped streams
+    reset_generation: u64,
+}
+
+/// in-process replacement for a toxiproxy between the geyser stream and the consumer; cheap to clone
+///
+/// the config can be changed at runtime and applies to all streams wrapped by this injector
+///
+/// see ``GrpcSourceConfig::with_fault_injector``
+#[derive(Clone)]
+pub struct FaultInjector {
+    inner: Arc<Mutex<FaultInjectorInner>>,
+    control_tx: Arc<watch::Sender<FaultControl>>,
+}
+
+struct FaultInjectorInner {
+    config: FaultConfig,
+    stats: FaultStats,
+    rng: XorShiftRng,
+}
+
+enum FaultAction {
+    Drop,
+    Reset,
+    Deliver {
+        delay: Duration,
+        stall: Option<Duration>,
+        transmit: Duration,
+    },
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        FaultInjector::new(FaultConfig::default())
+    }
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultConfig) -> Self {
+        let rng = XorShiftRng::new(config.seed);
+        let (control_tx, _) = watch::channel(FaultControl::default());
+        FaultInjector {
+            inner: Arc::new(Mutex::new(FaultInjectorInner {
+                config,
+                stats: FaultStats::default(),
+                rng,
+            })),
+            control_tx: Arc::new(control_tx),
+        }
+    }
+
+    pub fn config(&self) -> FaultConfig {
+        self.inner
+            .lock()
+            .expect("fault injector lock poisoned")
+            .config
+            .clone()
+    }
+
+    /// replace the config; applies to the next message of all wrapped streams
+    pub fn set_config(&self, config: FaultConfig) {
+        self.inner
+            .lock()
+            .expect("fault injector lock poisoned")
+            .config = config;
+    }
+
+    pub fn update_config(&self, update: impl FnOnce(&mut FaultConfig)) {
+        update(
+            &mut self
+                .inner
+                .lock()
+                .expect("fault injector lock poisoned")
+                .config,
+        );
+    }
+
+    pub fn stats(&self) -> FaultStats {
+        self.inner
+            .lock()
+            .expect("fault injector lock poisoned")
+            .stats
+    }
+
+    /// stop delivering messages on all wrapped streams until ``resume`` is called
+    pub fn stall(&self) {
+        self.control_tx
+            .send_modify(|control| control.stalled = true);
+    }
+
+    pub fn resume(&self) {
+        self.control_tx
+            .send_modify(|control| control.stalled = false);
+    }
+
+    /// terminate all currently wrapped streams with an error; messages already in flight are delivered first
+    pub fn reset_streams(&self) {
+        self.control_tx
+            .send_modify(|control| control.reset_generation += 1);
+    }
+
+    /// wrap a geyser stream; the upstream is consumed by a separate task which ends when the returned stream is dropped
+    pub fn inject<S, T>(
+        &self,
+        upstream: S,
+    ) -> impl Stream<Item = Result<T, Status>> + Send + 'static
+    where
+        S: Stream<Item = Result<T, Status>> + Send + 'static,
+        T: prost::Message + Send + 'static,
+    {
+        let (delivery_tx, mut delivery_rx) = mpsc::channel::<(Instant, Result<T, Status>)>(1024);
+        tokio::spawn(self.clone().pump(upstream, delivery_tx));
+
+        stream! {
+            while let Some((deadline, item)) = delivery_rx.recv().await {
+                sleep_until(deadline).await;
+                yield item;
+            }
+        }
+    }
+
+    async fn pump<S, T>(self, upstream: S, delivery_tx: mpsc::Sender<(Instant, Result<T, Status>)>)
+    where
+        S: Stream<Item = Result<T, Status>> + Send + 'static,
+        T: prost::Message + Send + 'static,
+    {
+        let mut upstream = std::pin::pin!(upstream);
+        let mut control_rx = self.control_tx.subscribe();
+        let reset_generation = control_rx.borrow_and_update().reset_generation;
+        // deadline of the previous message; keeps the order under jitter
+        let mut last_deadline = Instant::now();
+        // the link is busy transmitting until this time
+        let mut link_free_at = Instant::now();
+
+        loop {
+            if control_rx.borrow().stalled {
+                select! {
+                    _ = delivery_tx.closed() => return,
+                    _ = control_rx.wait_for(|control| !control.stalled || control.reset_generation != reset_generation) => {}
+                }
+            }
+            if control_rx.borrow().reset_generation != reset_generation {
+                self.reset(&delivery_tx, last_deadline).await;
+                return;
+            }
+
+            let next = select! {
+                _ = delivery_tx.closed() => return,
+                _ = control_rx.changed() => continue,
+                next = upstream.next() => next,
+            };
+
+            let update = match next {
+                Some(Ok(update)) => update,
+                Some(Err(status)) => {
+                    let _ = delivery_tx.send((last_deadline, Err(status))).await;
+                    return;
+                }
+                None => return,
+            };
+
+            match self.decide(update.encoded_len()) {
+                FaultAction::Drop => {
+                    continue;
+                }
+                FaultAction::Reset => {
+                    self.reset(&delivery_tx, last_deadline).await;
+                    return;
+                }
+                FaultAction::Deliver {
+                    delay,
+                    stall,
+                    transmit,
+                } => {
+                    if let Some(stall) = stall {
+                        debug!("fault injection: stall stream for {:?}", stall);
+                        select! {
+                            _ = delivery_tx.closed() => return,
+                            _ = sleep(stall) => {}
+                        }
+                    }
+                    if !transmit.is_zero() {
+                        link_free_at = link_free_at.max(Instant::now()) + transmit;
+                        select! {
+                            _ = delivery_tx.closed() => return,
+                            _ = sleep_until(link_free_at) => {}
+                        }
+                    }
+                    last_deadline = last_deadline.max(Instant::now() + delay);
+                    if delivery_tx.send((last_deadline, Ok(update))).await.is_err() {
+                        return;
+                    }
+                    self.inner
+                        .lock()
+                        .expect("fault injector lock poisoned")
+                        .stats
+                        .delivered += 1;
+                }
+            }
+        }
+    }
+
+    async fn reset<T>(
+        &self,
+        delivery_tx: &mpsc::Sender<(Instant, Result<T, Status>)>,
+        deadline: Instant,
+    ) {
+        debug!("fault injection: reset stream");
+        self.inner
+            .lock()
+            .expect("fault injector lock poisoned")
+            .stats
+            .resets += 1;
+        let _ = delivery_tx
+            .send((
+                deadline,
+                Err(Status::unavailable("fault injection: stream reset")),
+            ))
+            .await;
+    }
+
+    fn decide(&self, encoded_len: usize) -> FaultAction {
+        let mut inner = self.inner.lock().expect("fault injector lock poisoned");
+        let FaultInjectorInner { config, stats, rng } = &mut *inner;
+
+        if rng.chance(config.drop_probability) {
+            stats.dropped += 1;
+            return FaultAction::Drop;
+        }
+        if rng.chance(config.reset_probability) {
+            return FaultAction::Reset;
+        }
+        let stall = rng
+            .chance(config.stall_probability)
+            .then_some(config.stall_duration);
+        if stall.is_some() {
+            stats.stalls += 1;
+        }
+        let delay = config.latency + config.jitter.mul_f64(rng.next_f64());
+        let transmit = config
+            .bandwidth_bytes_per_sec
+            .map(|bytes_per_sec| {
+                Duration::from_secs_f64(encoded_len as f64 / bytes_per_sec.max(1) as f64)
+            })
+            .unwrap_or_default();
+
+        FaultAction::Deliver {
+            delay,
+            stall,
+            transmit,
+        }
+    }
+}
+
+// not cryptographically secure; good enough to pick faults
+struct XorShiftRng(u64);
+
+impl XorShiftRng {
+    fn new(seed: u64) -> Self {
+        let seed = if seed == 0 {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_nanos() as u64)
+                .unwrap_or(1)
+        } else {
+            seed
+        };
+        // state must not be zero
+        XorShiftRng(seed | 1)
+    }
+
+    const fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // uniform in [0, 1)
+    const fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    const fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::mock_slot_update;
+    use crate::yellowstone_extractors::update_slot;
+    use futures::stream;
+    use solana_clock::Slot;
+    use yellowstone_grpc_proto::geyser::SubscribeUpdate;
+    use yellowstone_grpc_proto::tonic::Code;
+
+    fn slot_updates(
+        slots: std::ops::Range<Slot>,
+    ) -> impl Stream<Item = Result<SubscribeUpdate, Status>> + Send + 'static {
+        stream::iter(slots.map(|slot| Ok(mock_slot_update(slot)))).chain(stream::pending())
+    }
+
+    fn slot_of(item: Option<Result<SubscribeUpdate, Status>>) -> Slot {
+        let update = item.expect("stream ended").expect("unexpected error");
+        update.update_oneof.as_ref().and_then(update_slot).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_latency_jitter_and_bandwidth() {
+        let injector = FaultInjector::new(FaultConfig {
+            latency: Duration::from_millis(100),
+            jitter: Duration::from_millis(50),
+            seed: 42,
+            ..FaultConfig::default()
+        });
+        let mut faulty = std::pin::pin!(injector.inject(slot_updates(0..10)));
+
+        let started_at = Instant::now();
+        for slot in 0..10 {
+            assert_eq!(slot_of(faulty.next().await), slot);
+        }
+        // latency applies per message but does not limit the throughput
+        assert!(started_at.elapsed() >= Duration::from_millis(100));
+        assert!(started_at.elapsed() <= Duration::from_millis(150));
+
+        let encoded_len = mock_slot_update(10).encoded_len() as u64;
+        injector.set_config(FaultConfig {
+            bandwidth_bytes_per_sec: Some(encoded_len * 10),
+            ..FaultConfig::default()
+        });
+        let mut throttled = std::pin::pin!(injector.inject(slot_updates(10..30)));
+        let started_at = Instant::now();
+        for slot in 10..30 {
+            assert_eq!(slot_of(throttled.next().await), slot);
+        }
+        assert!(started_at.elapsed() >= Duration::from_millis(1990));
+        assert_eq!(injector.stats().delivered, 30);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drop_and_reset() {
+        let injector = FaultInjector::new(FaultConfig {
+            drop_probability: 0.5,
+            seed: 7,
+            ..FaultConfig::default()
+        });
+        let received: Vec<Slot> = injector
+            .inject(stream::iter(
+                (0..100).map(|slot| Ok(mock_slot_update(slot))),
+            ))
+            .map(|item| slot_of(Some(item)))
+            .collect()
+            .await;
+        assert!(received.windows(2).all(|w| w[0] < w[1]));
+        assert!(received.len() > 25 && received.len() < 75);
+        assert_eq!(injector.stats().dropped as usize, 100 - received.len());
+
+        injector.set_config(FaultConfig::default());
+        let mut faulty = std::pin::pin!(injector.inject(slot_updates(0..1)));
+        assert_eq!(slot_of(faulty.next().await), 0);
+        injector.reset_streams();
+        let status = faulty.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert!(faulty.next().await.is_none());
+        assert_eq!(injector.stats().resets, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stall_and_resume() {
+        let injector = FaultInjector::default();
+        injector.stall();
+        let mut faulty = std::pin::pin!(injector.inject(slot_updates(0..3)));
+        assert!(tokio::time::timeout(Duration::from_secs(60), faulty.next())
+            .await
+            .is_err());
+
+        injector.resume();
+        for slot in 0..3 {
+            assert_eq!(slot_of(faulty.next().await), slot);
+        }
+    }
+}
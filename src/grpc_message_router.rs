@@ -0,0 +1,270 @@
+use solana_pubkey::Pubkey;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::SubscribeUpdate;
+
+use crate::channel_plugger::{
+    spawn_fanout_hub, FanoutHub, FanoutReceiver, SubscriberId, SubscriberPolicy, SubscriberStats,
+};
+use crate::Message;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum UpdateKind {
+    Account,
+    Slot,
+    Transaction,
+    TransactionStatus,
+    Block,
+    BlockMeta,
+    Entry,
+    Ping,
+    Pong,
+}
+
+impl UpdateKind {
+    pub const fn of(update_oneof: &UpdateOneof) -> Self {
+        match update_oneof {
+            UpdateOneof::Account(_) => UpdateKind::Account,
+            UpdateOneof::Slot(_) => UpdateKind::Slot,
+            UpdateOneof::Transaction(_) => UpdateKind::Transaction,
+            UpdateOneof::TransactionStatus(_) => UpdateKind::TransactionStatus,
+            UpdateOneof::Block(_) => UpdateKind::Block,
+            UpdateOneof::BlockMeta(_) => UpdateKind::BlockMeta,
+            UpdateOneof::Entry(_) => UpdateKind::Entry,
+            UpdateOneof::Ping(_) => UpdateKind::Ping,
+            UpdateOneof::Pong(_) => UpdateKind::Pong,
+        }
+    }
+}
+
+/// declarative match on geyser updates; all non-empty criteria must match, any value of a criterion is sufficient
+///
+/// ``Message::Connecting`` matches always so that every subscriber learns about reconnects
+#[derive(Clone, Debug, Default)]
+pub struct MessageMatch {
+    pub kinds: Vec<UpdateKind>,
+    // name of the filter in the subscribe request (see `SubscribeUpdate::filters`)
+    pub filter_names: Vec<String>,
+    // owner of an updated account
+    pub account_owners: Vec<Pubkey>,
+    // updated account or account referenced by a transaction
+    pub pubkeys: Vec<Pubkey>,
+}
+
+impl MessageMatch {
+    pub fn kind(kind: UpdateKind) -> Self {
+        MessageMatch {
+            kinds: vec![kind],
+            ..Default::default()
+        }
+    }
+
+    pub fn matches(&self, message: &Message) -> bool {
+        match message {
+            Message::GeyserSubscribeUpdate(update) => self.matches_update(update),
+            Message::Connecting(_) => true,
+        }
+    }
+
+    fn matches_update(&self, update: &SubscribeUpdate) -> bool {
+        let Some(update_oneof) = &update.update_oneof else {
+            return false;
+        };
+
+        if !self.kinds.is_empty() && !self.kinds.contains(&UpdateKind::of(update_oneof)) {
+            return false;
+        }
+
+        if !self.filter_names.is_empty()
+            && !update
+                .filters
+                .iter()
+                .any(|name| self.filter_names.contains(name))
+        {
+            return false;
+        }
+
+        if !self.account_owners.is_empty() {
+            let owner = match update_oneof {
+                UpdateOneof::Account(account) => account.account.as_ref().map(|info| &info.owner),
+                _ => None,
+            };
+            if !owner.is_some_and(|owner| contains_key(&self.account_owners, owner)) {
+                return false;
+            }
+        }
+
+        if !self.pubkeys.is_empty() && !self.matches_pubkeys(update_oneof) {
+            return false;
+        }
+
+        true
+    }
+
+    fn matches_pubkeys(&self, update_oneof: &UpdateOneof) -> bool {
+        match update_oneof {
+            UpdateOneof::Account(account) => account
+                .account
+                .as_ref()
+                .is_some_and(|info| contains_key(&self.pubkeys, &info.pubkey)),
+            UpdateOneof::Transaction(transaction) => {
+                let Some(info) = &transaction.transaction else {
+                    return false;
+                };
+                let static_keys = info
+                    .transaction
+                    .as_ref()
+                    .and_then(|tx| tx.message.as_ref())
+                    .map(|message| message.account_keys.iter())
+                    .into_iter()
+                    .flatten();
+                let loaded_keys = info
+                    .meta
+                    .as_ref()
+                    .map(|meta| {
+                        meta.loaded_writable_addresses
+                            .iter()
+                            .chain(meta.loaded_readonly_addresses.iter())
+                    })
+                    .into_iter()
+                    .flatten();
+                static_keys
+                    .chain(loaded_keys)
+                    .any(|key| contains_key(&self.pubkeys, key))
+            }
+            _ => false,
+        }
+    }
+}
+
+fn contains_key(pubkeys: &[Pubkey], key: &[u8]) -> bool {
+    pubkeys.iter().any(|pubkey| pubkey.as_ref() == key)
+}
+
+/// dispatches the messages of one subscription to many internal consumers; cheap to clone
+#[derive(Clone)]
+pub struct MessageRouter {
+    hub: FanoutHub<Message>,
+}
+
+impl MessageRouter {
+    pub fn route(
+        &self,
+        message_match: MessageMatch,
+        policy: SubscriberPolicy,
+    ) -> (SubscriberId, FanoutReceiver<Message>) {
+        self.hub
+            .attach_with_filter(policy, move |message| message_match.matches(message))
+    }
+
+    pub fn route_with_predicate(
+        &self,
+        predicate: impl Fn(&Message) -> bool + Send + 'static,
+        policy: SubscriberPolicy,
+    ) -> (SubscriberId, FanoutReceiver<Message>) {
+        self.hub.attach_with_filter(policy, predicate)
+    }
+
+    pub fn unroute(&self, id: SubscriberId) -> bool {
+        self.hub.detach(id)
+    }
+
+    pub fn stats(&self) -> Vec<SubscriberStats> {
+        self.hub.stats()
+    }
+}
+
+/// use with the receiver of ``create_geyser_autoconnection_task``; see ``spawn_fanout_hub``
+pub fn spawn_message_router(
+    upstream: mpsc::Receiver<Message>,
+    exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, MessageRouter) {
+    let (jh_hub, hub) = spawn_fanout_hub(upstream, exit_notify);
+    (jh_hub, MessageRouter { hub })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_account_update, mock_message, mock_slot_update};
+
+    fn account_message(pubkey: Pubkey, owner: Pubkey) -> Message {
+        let mut update = mock_account_update(pubkey, owner, 0, 0, 0, vec![]);
+        update.filters = vec!["accounts".to_string()];
+        mock_message(update)
+    }
+
+    #[test]
+    fn test_message_match() {
+        let program = Pubkey::new_from_array([1; 32]);
+        let other_program = Pubkey::new_from_array([2; 32]);
+        let account = Pubkey::new_from_array([3; 32]);
+        let account_of_program = account_message(account, program);
+        let account_of_other_program = account_message(account, other_program);
+        let program_account = account_message(program, other_program);
+        let slot = mock_message(mock_slot_update(42));
+
+        let by_owner = MessageMatch {
+            account_owners: vec![program],
+            ..Default::default()
+        };
+        assert!(by_owner.matches(&account_of_program));
+        assert!(!by_owner.matches(&account_of_other_program));
+        assert!(!by_owner.matches(&slot));
+        assert!(by_owner.matches(&Message::Connecting(1)));
+
+        let by_pubkey_and_filter = MessageMatch {
+            filter_names: vec!["accounts".to_string()],
+            pubkeys: vec![account],
+            ..Default::default()
+        };
+        assert!(by_pubkey_and_filter.matches(&account_of_other_program));
+        assert!(!by_pubkey_and_filter.matches(&program_account));
+
+        let slots = MessageMatch::kind(UpdateKind::Slot);
+        assert!(slots.matches(&slot));
+        assert!(!slots.matches(&account_of_program));
+    }
+
+    #[tokio::test]
+    async fn test_router() {
+        let program = Pubkey::new_from_array([1; 32]);
+        let (tx, rx) = mpsc::channel(8);
+        let (_exit_tx, exit_rx) = broadcast::channel(1);
+        let (jh_router, router) = spawn_message_router(rx, exit_rx);
+
+        let policy = SubscriberPolicy::Blocking { capacity: 8 };
+        let (_, mut slots) = router.route(MessageMatch::kind(UpdateKind::Slot), policy);
+        let (_, mut accounts) = router.route_with_predicate(
+            |message| match message {
+                Message::GeyserSubscribeUpdate(update) => {
+                    matches!(update.update_oneof, Some(UpdateOneof::Account(_)))
+                }
+                Message::Connecting(_) => false,
+            },
+            policy,
+        );
+
+        tx.send(mock_message(mock_slot_update(42))).await.unwrap();
+        tx.send(account_message(Pubkey::new_from_array([3; 32]), program))
+            .await
+            .unwrap();
+        tx.send(mock_message(mock_slot_update(43))).await.unwrap();
+        drop(tx);
+        jh_router.await.unwrap();
+
+        let mut received_slots = 0;
+        while let Some(message) = slots.recv().await {
+            assert!(MessageMatch::kind(UpdateKind::Slot).matches(&message));
+            received_slots += 1;
+        }
+        assert_eq!(received_slots, 2);
+
+        let mut received_accounts = 0;
+        while accounts.recv().await.is_some() {
+            received_accounts += 1;
+        }
+        assert_eq!(received_accounts, 1);
+    }
+}
@@ -6,7 +6,7 @@ use futures::{Stream, StreamExt};
 use log::{debug, info, log, trace, warn, Level};
 use tokio::task::JoinHandle;
 use tokio::time::{sleep, timeout};
-use yellowstone_grpc_client::GeyserGrpcClientResult;
+use yellowstone_grpc_client::{GeyserGrpcClientError, GeyserGrpcClientResult};
 use yellowstone_grpc_proto::geyser::{SubscribeRequest, SubscribeUpdate};
 use yellowstone_grpc_proto::tonic::Status;
 
@@ -48,6 +48,7 @@ pub fn create_geyser_reconnecting_stream(
                         let request_timeout = grpc_source.timeouts.as_ref().map(|t| t.request_timeout);
                         let subscribe_timeout = grpc_source.timeouts.as_ref().map(|t| t.subscribe_timeout);
                         let subscribe_filter = subscribe_filter.clone();
+                        let fault_injector = grpc_source.fault_injector.clone();
                         log!(if attempt > 1 { Level::Warn } else { Level::Debug }, "Connecting attempt #{} to {}", attempt, addr);
                         async move {
 
@@ -69,7 +70,11 @@ pub fn create_geyser_reconnecting_stream(
                                 client.subscribe_once(subscribe_filter)).await;
 
                             // maybe not optimal
-                            subscribe_result.map_err(|_| Status::unknown("unspecific subscribe timeout"))?
+                            let geyser_stream = subscribe_result.map_err(|_| Status::unknown("unspecific subscribe timeout"))??;
+                            Ok::<_, GeyserGrpcClientError>(match fault_injector {
+                                Some(fault_injector) => fault_injector.inject(geyser_stream).boxed(),
+                                None => geyser_stream.boxed(),
+                            })
                         }
                     });
 
@@ -139,10 +144,56 @@ pub fn create_geyser_reconnecting_stream(
 
 #[cfg(test)]
 mod tests {
+    use crate::fault_injection::FaultInjector;
+    use crate::testing::{mock_slot_update, MockGeyserServer, MockSubscription};
+    use crate::yellowstone_extractors::update_slot;
     use crate::GrpcConnectionTimeouts;
+    use solana_clock::Slot;
 
     use super::*;
 
+    // skips the Connecting messages
+    async fn next_slot(stream: &mut (impl Stream<Item = Message> + Unpin)) -> Option<Slot> {
+        loop {
+            match stream.next().await? {
+                Message::GeyserSubscribeUpdate(update) => {
+                    return update.update_oneof.as_ref().and_then(update_slot)
+                }
+                Message::Connecting(_) => continue,
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_on_injected_reset() {
+        let server = MockGeyserServer::start([
+            MockSubscription::new().emit(mock_slot_update(1)),
+            MockSubscription::new().emit(mock_slot_update(2)),
+        ])
+        .await
+        .unwrap();
+        let fault_injector = FaultInjector::default();
+        let timeouts = GrpcConnectionTimeouts {
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(10),
+            subscribe_timeout: Duration::from_secs(5),
+            receive_timeout: Duration::from_secs(10),
+        };
+        let mut stream = std::pin::pin!(create_geyser_reconnecting_stream(
+            server
+                .grpc_source(timeouts)
+                .with_fault_injector(fault_injector.clone()),
+            SubscribeRequest::default(),
+        ));
+
+        assert_eq!(next_slot(&mut stream).await, Some(1));
+        fault_injector.reset_streams();
+        assert_eq!(next_slot(&mut stream).await, Some(2));
+        assert_eq!(server.subscribe_calls(), 2);
+        assert_eq!(fault_injector.stats().resets, 1);
+        assert_eq!(fault_injector.stats().delivered, 2);
+    }
+
     #[tokio::test]
     async fn test_debug_no_secrets() {
         let timeout_config = GrpcConnectionTimeouts {
@@ -17,9 +17,8 @@ use yellowstone_grpc_proto::geyser::{SubscribeRequest, SubscribeUpdate};
 use yellowstone_grpc_proto::tonic::service::Interceptor;
 use yellowstone_grpc_proto::tonic::Status;
 
-use crate::yellowstone_grpc_util::{
-    connect_with_timeout_with_buffers, GeyserGrpcClientBufferConfig,
-};
+use crate::connection_stats::ConnectionStats;
+use crate::yellowstone_grpc_util::{connect_with_failover, GeyserGrpcClientBufferConfig};
 use crate::{Attempt, GrpcSourceConfig, Message};
 
 enum ConnectionState<
@@ -107,18 +106,41 @@ impl Display for LogTag {
     }
 }
 
+// compat
+pub fn create_geyser_autoconnection_task_with_log_tag(
+    grpc_source: GrpcSourceConfig,
+    subscribe_filter: SubscribeRequest,
+    mpsc_downstream: mpsc::Sender<Message>,
+    exit_notify: broadcast::Receiver<()>,
+    subscribe_filter_update_rx: Option<mpsc::Receiver<SubscribeRequest>>,
+    log_tag: &Option<LogTag>,
+) -> JoinHandle<()> {
+    create_geyser_autoconnection_task_with_stats(
+        grpc_source,
+        subscribe_filter,
+        mpsc_downstream,
+        exit_notify,
+        subscribe_filter_update_rx,
+        log_tag,
+        ConnectionStats::default(),
+    )
+}
+
 /// connect to grpc source performing autoconnect if required,
 /// returns mpsc channel; task will abort on fatal error
 /// will shut down when receiver is dropped
 ///
+/// the hostname gets re-resolved on every (re)connect; the connected IP is reported via `connection_stats`
+///
 /// read this for argument: http://www.randomhacks.net/2019/03/08/should-rust-channels-panic-on-send/
-pub fn create_geyser_autoconnection_task_with_log_tag(
+pub fn create_geyser_autoconnection_task_with_stats(
     grpc_source: GrpcSourceConfig,
     subscribe_filter: SubscribeRequest,
     mpsc_downstream: mpsc::Sender<Message>,
     mut exit_notify: broadcast::Receiver<()>,
     mut subscribe_filter_update_rx: Option<mpsc::Receiver<SubscribeRequest>>,
     log_tag: &Option<LogTag>,
+    connection_stats: ConnectionStats,
 ) -> JoinHandle<()> {
     let log_tag = log_tag
         .as_ref()
@@ -140,6 +162,25 @@ pub fn create_geyser_autoconnection_task_with_log_tag(
         'main_loop: loop {
             state = match state {
                 ConnectionState::NotConnected(attempt) => {
+                    // let downstream know about every (re)connect attempt if requested
+                    if grpc_source.connecting_messages {
+                        let fut_send = mpsc_downstream.send(Message::Connecting(attempt));
+                        match await_or_exit(fut_send, exit_notify.recv(), log_tag.clone()).await {
+                            MaybeExit::Continue(Ok(())) => {}
+                            MaybeExit::Continue(Err(_send_error)) => {
+                                state = ConnectionState::FatalError(
+                                    attempt,
+                                    FatalErrorReason::DownstreamChannelClosed,
+                                );
+                                continue 'main_loop;
+                            }
+                            MaybeExit::Exit => {
+                                state = ConnectionState::GracefulShutdown;
+                                continue 'main_loop;
+                            }
+                        }
+                    }
+
                     let addr = grpc_source.grpc_addr.clone();
                     let token = grpc_source.grpc_x_token.clone();
                     let config = grpc_source.tls_config.clone();
@@ -165,8 +206,24 @@ pub fn create_geyser_autoconnection_task_with_log_tag(
                         buffer_config, log_tag
                     );
 
+                    connection_stats.on_connect_attempt();
+
                     let connection_handler = |connect_result| match connect_result {
-                        Ok(client) => ConnectionState::Connecting(attempt, client),
+                        Ok((client, connected_addr)) => {
+                            match connected_addr {
+                                Some(connected_addr) => debug!(
+                                    "Connected: to={}, addr={}{}",
+                                    grpc_source, connected_addr, log_tag
+                                ),
+                                // lazy client; connection errors surface on subscribe
+                                None => debug!(
+                                    "Not connected yet - connect on subscribe: to={}{}",
+                                    grpc_source, log_tag
+                                ),
+                            }
+                            connection_stats.on_connected(connected_addr);
+                            ConnectionState::Connecting(attempt, client)
+                        }
                         Err(GeyserGrpcBuilderError::MetadataValueError(_)) => {
                             ConnectionState::FatalError(
                                 attempt + 1,
@@ -182,7 +239,7 @@ pub fn create_geyser_autoconnection_task_with_log_tag(
                         }
                     };
 
-                    let fut_connector = connect_with_timeout_with_buffers(
+                    let fut_connector = connect_with_failover(
                         addr,
                         token,
                         config,
@@ -225,6 +282,12 @@ pub fn create_geyser_autoconnection_task_with_log_tag(
                                                     grpc_source, attempt, log_tag
                                                 );
                                             }
+                                            let geyser_stream = match &grpc_source.fault_injector {
+                                                Some(fault_injector) => {
+                                                    fault_injector.inject(geyser_stream).boxed()
+                                                }
+                                                None => geyser_stream.boxed(),
+                                            };
                                             ConnectionState::Ready(
                                                 geyser_stream,
                                                 geyser_subscribe_tx,
@@ -351,6 +414,7 @@ pub fn create_geyser_autoconnection_task_with_log_tag(
                                 match geyser_stream_res {
                                     Ok(Some(Ok(update_message))) => {
                                         trace!("> recv update message: from={}{}", grpc_source, log_tag);
+                                        connection_stats.on_update_received(&update_message);
                                         // note: first send never blocks as the mpsc channel has capacity 1
                                         let warning_threshold = if messages_forwarded == 1 {
                                             Duration::from_millis(3000)
@@ -373,6 +437,7 @@ pub fn create_geyser_autoconnection_task_with_log_tag(
                                         match mpsc_downstream_result {
                                             Ok(()) => {
                                                 messages_forwarded += 1;
+                                                connection_stats.on_message_forwarded();
                                                 if messages_forwarded == 1 {
                                                     // note: first send never blocks - do not print time as this is a lie
                                                     trace!("queued first update message{}", log_tag);
@@ -403,6 +468,7 @@ pub fn create_geyser_autoconnection_task_with_log_tag(
                                                 match mpsc_downstream_result {
                                                     Ok(()) => {
                                                         messages_forwarded += 1;
+                                                        connection_stats.on_message_forwarded();
                                                         trace!(
                                                             "queued delayed update message: #={}, elapsed={:.02}ms{}",
                                                             messages_forwarded,
@@ -522,10 +588,411 @@ where
 
 #[cfg(test)]
 mod tests {
+    use crate::fault_injection::FaultInjector;
+    use crate::testing::{mock_slot_update, MockGeyserServer, MockSubscription};
+    use crate::yellowstone_extractors::update_slot;
     use crate::GrpcConnectionTimeouts;
+    use solana_clock::Slot;
+    use std::collections::HashMap;
+    use yellowstone_grpc_proto::geyser::SubscribeRequestFilterSlots;
 
     use super::*;
 
+    fn timeouts() -> GrpcConnectionTimeouts {
+        GrpcConnectionTimeouts {
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(10),
+            subscribe_timeout: Duration::from_secs(5),
+            receive_timeout: Duration::from_secs(10),
+        }
+    }
+
+    fn slots_filter(name: &str) -> SubscribeRequest {
+        SubscribeRequest {
+            slots: HashMap::from([(name.to_string(), SubscribeRequestFilterSlots::default())]),
+            ..Default::default()
+        }
+    }
+
+    async fn recv_slot(rx: &mut mpsc::Receiver<Message>) -> Option<Slot> {
+        let message = timeout(Duration::from_secs(10), rx.recv())
+            .await
+            .expect("no message within timeout")?;
+        match message {
+            Message::GeyserSubscribeUpdate(update) => {
+                update.update_oneof.as_ref().and_then(update_slot)
+            }
+            Message::Connecting(_) => None,
+        }
+    }
+
+    async fn wait_until(condition: impl Fn() -> bool) {
+        timeout(Duration::from_secs(10), async {
+            while !condition() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("condition not met within timeout");
+    }
+
+    fn spawn_task(
+        server: &MockGeyserServer,
+        timeouts: GrpcConnectionTimeouts,
+        subscribe_filter_update_rx: Option<mpsc::Receiver<SubscribeRequest>>,
+    ) -> (
+        JoinHandle<()>,
+        mpsc::Receiver<Message>,
+        broadcast::Sender<()>,
+    ) {
+        let (exit_tx, exit_rx) = broadcast::channel(1);
+        let (message_tx, message_rx) = mpsc::channel(16);
+        let jh_task = create_geyser_autoconnection_task_with_updater(
+            server.grpc_source(timeouts),
+            slots_filter("initial"),
+            message_tx,
+            exit_rx,
+            subscribe_filter_update_rx,
+        );
+        (jh_task, message_rx, exit_tx)
+    }
+
+    async fn assert_task_finishes(jh_task: JoinHandle<()>) {
+        timeout(Duration::from_secs(10), jh_task)
+            .await
+            .expect("task did not finish")
+            .expect("task panicked");
+    }
+
+    #[tokio::test]
+    async fn test_connect_and_forward() {
+        let server = MockGeyserServer::start([
+            MockSubscription::new().emit_all((1..=3).map(mock_slot_update))
+        ])
+        .await
+        .unwrap();
+        let (exit_tx, exit_rx) = broadcast::channel(1);
+        let (message_tx, mut message_rx) = mpsc::channel(16);
+        let connection_stats = ConnectionStats::default();
+        let jh_task = create_geyser_autoconnection_task_with_stats(
+            server.grpc_source(timeouts()),
+            slots_filter("initial"),
+            message_tx,
+            exit_rx,
+            None,
+            &None,
+            connection_stats.clone(),
+        );
+
+        for slot in 1..=3 {
+            assert_eq!(recv_slot(&mut message_rx).await, Some(slot));
+        }
+        wait_until(|| connection_stats.snapshot().messages_forwarded == 3).await;
+        let stats = connection_stats.snapshot();
+        assert_eq!(stats.connected_addr, Some(server.local_addr()));
+        assert_eq!(stats.connect_attempts, 1);
+        assert_eq!(stats.messages_forwarded, 3);
+        assert_eq!(server.received_requests(), vec![slots_filter("initial")]);
+
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_on_tonic_error_and_stream_end() {
+        let server = MockGeyserServer::start([
+            MockSubscription::new()
+                .emit(mock_slot_update(1))
+                .fail(Status::internal("scripted failure")),
+            MockSubscription::new().emit(mock_slot_update(2)).close(),
+            MockSubscription::new().emit(mock_slot_update(3)),
+        ])
+        .await
+        .unwrap();
+        let (jh_task, mut message_rx, exit_tx) = spawn_task(&server, timeouts(), None);
+
+        for slot in 1..=3 {
+            assert_eq!(recv_slot(&mut message_rx).await, Some(slot));
+        }
+        assert_eq!(server.subscribe_calls(), 3);
+
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_on_injected_reset() {
+        let server = MockGeyserServer::start([
+            MockSubscription::new().emit(mock_slot_update(1)),
+            MockSubscription::new().emit(mock_slot_update(2)),
+        ])
+        .await
+        .unwrap();
+        let fault_injector = FaultInjector::default();
+        let (exit_tx, exit_rx) = broadcast::channel(1);
+        let (message_tx, mut message_rx) = mpsc::channel(16);
+        let jh_task = create_geyser_autoconnection_task_with_mpsc(
+            server
+                .grpc_source(timeouts())
+                .with_fault_injector(fault_injector.clone()),
+            slots_filter("initial"),
+            message_tx,
+            exit_rx,
+        );
+
+        assert_eq!(recv_slot(&mut message_rx).await, Some(1));
+        fault_injector.reset_streams();
+        assert_eq!(recv_slot(&mut message_rx).await, Some(2));
+        assert_eq!(server.subscribe_calls(), 2);
+        assert_eq!(fault_injector.stats().resets, 1);
+
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_on_receive_timeout() {
+        let server = MockGeyserServer::start([
+            MockSubscription::new()
+                .emit(mock_slot_update(1))
+                .stop_responding(),
+            MockSubscription::new().emit(mock_slot_update(2)),
+        ])
+        .await
+        .unwrap();
+        let timeouts = GrpcConnectionTimeouts {
+            receive_timeout: Duration::from_millis(300),
+            ..timeouts()
+        };
+        let (jh_task, mut message_rx, exit_tx) = spawn_task(&server, timeouts, None);
+
+        assert_eq!(recv_slot(&mut message_rx).await, Some(1));
+        assert_eq!(recv_slot(&mut message_rx).await, Some(2));
+        assert_eq!(server.subscribe_calls(), 2);
+
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_on_subscribe_timeout_and_error() {
+        let server = MockGeyserServer::start([
+            MockSubscription::new().delay_subscribe(Duration::from_secs(60)),
+            MockSubscription::new().reject_subscribe(Status::unavailable("scripted rejection")),
+            MockSubscription::new().emit(mock_slot_update(1)),
+        ])
+        .await
+        .unwrap();
+        let timeouts = GrpcConnectionTimeouts {
+            subscribe_timeout: Duration::from_millis(300),
+            ..timeouts()
+        };
+        let started_at = Instant::now();
+        let (jh_task, mut message_rx, exit_tx) = spawn_task(&server, timeouts, None);
+
+        assert_eq!(recv_slot(&mut message_rx).await, Some(1));
+        // subscribe timeout of 0.3s, then backoff of 2.25s and 3.375s
+        let elapsed = started_at.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(5_925),
+            "elapsed={:?}",
+            elapsed
+        );
+        assert!(
+            elapsed < Duration::from_millis(6_100),
+            "elapsed={:?}",
+            elapsed
+        );
+        assert_eq!(server.subscribe_calls(), 3);
+
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+    }
+
+    #[tokio::test]
+    async fn test_filter_update() {
+        let server = MockGeyserServer::start([MockSubscription::new().emit(mock_slot_update(1))])
+            .await
+            .unwrap();
+        let (filter_tx, filter_rx) = mpsc::channel(1);
+        let (jh_task, mut message_rx, exit_tx) = spawn_task(&server, timeouts(), Some(filter_rx));
+
+        assert_eq!(recv_slot(&mut message_rx).await, Some(1));
+        filter_tx.send(slots_filter("updated")).await.unwrap();
+        wait_until(|| server.received_requests().len() == 2).await;
+        assert_eq!(
+            server.received_requests(),
+            vec![slots_filter("initial"), slots_filter("updated")]
+        );
+        assert_eq!(server.subscribe_calls(), 1);
+
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_filter_update_rejected() {
+        let server = MockGeyserServer::start([
+            MockSubscription::new()
+                .emit(mock_slot_update(1))
+                .reject_filter_updates(Status::invalid_argument("failed to create filter")),
+            MockSubscription::new().emit(mock_slot_update(2)),
+        ])
+        .await
+        .unwrap();
+        let (filter_tx, filter_rx) = mpsc::channel(1);
+        let (jh_task, mut message_rx, exit_tx) = spawn_task(&server, timeouts(), Some(filter_rx));
+
+        assert_eq!(recv_slot(&mut message_rx).await, Some(1));
+        filter_tx.send(slots_filter("invalid")).await.unwrap();
+        // reconnect uses the updated filter
+        assert_eq!(recv_slot(&mut message_rx).await, Some(2));
+        assert_eq!(
+            server.received_requests(),
+            vec![slots_filter("initial"), slots_filter("invalid")]
+        );
+        assert_eq!(server.subscribe_calls(), 2);
+
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+    }
+
+    #[tokio::test]
+    async fn test_exit_in_each_state() {
+        // before connecting
+        let server = MockGeyserServer::start(Vec::<MockSubscription>::new())
+            .await
+            .unwrap();
+        let (jh_task, _message_rx, exit_tx) = spawn_task(&server, timeouts(), None);
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+
+        // waiting for subscribe response
+        let server = MockGeyserServer::start([
+            MockSubscription::new().delay_subscribe(Duration::from_secs(60))
+        ])
+        .await
+        .unwrap();
+        let (jh_task, _message_rx, exit_tx) = spawn_task(&server, timeouts(), None);
+        wait_until(|| server.subscribe_calls() == 1).await;
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+
+        // waiting for reconnect after subscribe error
+        let server = MockGeyserServer::start([
+            MockSubscription::new().reject_subscribe(Status::unavailable("scripted rejection"))
+        ])
+        .await
+        .unwrap();
+        let (jh_task, _message_rx, exit_tx) = spawn_task(&server, timeouts(), None);
+        wait_until(|| server.subscribe_calls() == 1).await;
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+
+        // subscribed
+        let server = MockGeyserServer::start([MockSubscription::new().emit(mock_slot_update(1))])
+            .await
+            .unwrap();
+        let (jh_task, mut message_rx, exit_tx) = spawn_task(&server, timeouts(), None);
+        assert_eq!(recv_slot(&mut message_rx).await, Some(1));
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+
+        // waiting for reconnect after stream end
+        let server =
+            MockGeyserServer::start([MockSubscription::new().emit(mock_slot_update(1)).close()])
+                .await
+                .unwrap();
+        let (jh_task, mut message_rx, exit_tx) = spawn_task(&server, timeouts(), None);
+        assert_eq!(recv_slot(&mut message_rx).await, Some(1));
+        sleep(Duration::from_millis(100)).await;
+        exit_tx.send(()).unwrap();
+        assert_task_finishes(jh_task).await;
+    }
+
+    #[tokio::test]
+    async fn test_abort_on_downstream_closed() {
+        let server = MockGeyserServer::start([
+            MockSubscription::new().emit_all((1..=3).map(mock_slot_update))
+        ])
+        .await
+        .unwrap();
+        let (jh_task, message_rx, _exit_tx) = spawn_task(&server, timeouts(), None);
+        drop(message_rx);
+        assert_task_finishes(jh_task).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backoff_with_paused_time() {
+        let rejection =
+            MockSubscription::new().reject_subscribe(Status::unavailable("scripted rejection"));
+        let server = MockGeyserServer::start([
+            rejection.clone(),
+            rejection.clone(),
+            rejection,
+            MockSubscription::new().emit(mock_slot_update(1)),
+        ])
+        .await
+        .unwrap();
+        let (exit_tx, exit_rx) = broadcast::channel(1);
+        // no timeouts; the clock only advances while the task is sleeping in backoff
+        let (jh_task, mut message_rx) = create_geyser_autoconnection_task(
+            GrpcSourceConfig::new_simple(server.grpc_addr()),
+            slots_filter("initial"),
+            exit_rx,
+        );
+
+        let started_at = Instant::now();
+        let Some(Message::GeyserSubscribeUpdate(_)) = message_rx.recv().await else {
+            panic!("expected update");
+        };
+        // 1.5^2 + 1.5^3 + 1.5^4 seconds
+        let elapsed = started_at.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(10_687),
+            "elapsed={:?}",
+            elapsed
+        );
+        assert!(
+            elapsed < Duration::from_millis(10_800),
+            "elapsed={:?}",
+            elapsed
+        );
+        assert_eq!(server.subscribe_calls(), 4);
+
+        exit_tx.send(()).unwrap();
+        jh_task.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connecting_messages() {
+        let rejection =
+            MockSubscription::new().reject_subscribe(Status::unavailable("scripted rejection"));
+        let server = MockGeyserServer::start([
+            rejection.clone(),
+            rejection,
+            MockSubscription::new().emit(mock_slot_update(1)),
+        ])
+        .await
+        .unwrap();
+        let (exit_tx, exit_rx) = broadcast::channel(1);
+        let (jh_task, mut message_rx) = create_geyser_autoconnection_task(
+            GrpcSourceConfig::new_simple(server.grpc_addr()).with_connecting_messages(),
+            slots_filter("initial"),
+            exit_rx,
+        );
+
+        let mut attempts = vec![];
+        while let Some(Message::Connecting(attempt)) = message_rx.recv().await {
+            attempts.push(attempt);
+        }
+        assert_eq!(attempts, vec![1, 2, 3]);
+        assert_eq!(server.subscribe_calls(), 3);
+
+        exit_tx.send(()).unwrap();
+        jh_task.await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_debug_no_secrets() {
         let timeout_config = GrpcConnectionTimeouts {
@@ -0,0 +1,271 @@
+use std::collections::{BTreeMap, HashSet};
+
+use async_stream::stream;
+use futures::Stream;
+use log::{info, trace, warn};
+use solana_clock::Slot;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{SubscribeRequest, SubscribeUpdate};
+
+use crate::grpcmultiplex_fastestwins::{
+    merge_tagged, spawn_source_tasks, spawn_stream_forwarder, TaggedMessage,
+};
+use crate::{GrpcSourceConfig, Message};
+
+/// identity of an update independent of the source that delivered it
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DedupKey {
+    // (pubkey, write_version)
+    Account(Vec<u8>, u64),
+    // signature
+    Transaction(Vec<u8>),
+    // signature
+    TransactionStatus(Vec<u8>),
+    // (slot, status)
+    Slot(Slot, i32),
+    Block(Slot),
+    BlockMeta(Slot),
+    // (slot, index)
+    Entry(Slot, u64),
+}
+
+/// map the update to its dedup key and the slot used to prune the dedup window;
+/// returns None for updates which cannot be deduplicated (ping/pong)
+pub fn dedup_key(update: &SubscribeUpdate) -> Option<(Slot, DedupKey)> {
+    match update.update_oneof.as_ref()? {
+        UpdateOneof::Account(account) => {
+            let info = account.account.as_ref()?;
+            Some((
+                account.slot,
+                DedupKey::Account(info.pubkey.clone(), info.write_version),
+            ))
+        }
+        UpdateOneof::Transaction(tx) => {
+            let info = tx.transaction.as_ref()?;
+            Some((tx.slot, DedupKey::Transaction(info.signature.clone())))
+        }
+        UpdateOneof::TransactionStatus(tx_status) => Some((
+            tx_status.slot,
+            DedupKey::TransactionStatus(tx_status.signature.clone()),
+        )),
+        UpdateOneof::Slot(slot) => Some((slot.slot, DedupKey::Slot(slot.slot, slot.status))),
+        UpdateOneof::Block(block) => Some((block.slot, DedupKey::Block(block.slot))),
+        UpdateOneof::BlockMeta(meta) => Some((meta.slot, DedupKey::BlockMeta(meta.slot))),
+        UpdateOneof::Entry(entry) => Some((entry.slot, DedupKey::Entry(entry.slot, entry.index))),
+        // ping/pong
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DedupConfig {
+    // keys of slots older than highest seen slot minus window_slots get pruned
+    pub window_slots: u64,
+    // limit for the number of keys; oldest slots get pruned first but never the highest slot
+    pub max_entries: usize,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        DedupConfig {
+            window_slots: 32,
+            max_entries: 1_000_000,
+        }
+    }
+}
+
+/// bounded set of seen keys, pruned by slot
+pub struct DedupWindow {
+    config: DedupConfig,
+    seen: HashSet<DedupKey>,
+    keys_by_slot: BTreeMap<Slot, Vec<DedupKey>>,
+    highest_slot: Slot,
+}
+
+impl DedupWindow {
+    pub fn new(config: DedupConfig) -> Self {
+        DedupWindow {
+            config,
+            seen: HashSet::new(),
+            keys_by_slot: BTreeMap::new(),
+            highest_slot: 0,
+        }
+    }
+
+    /// returns true if the key was not seen before (first arrival wins)
+    pub fn insert(&mut self, slot: Slot, key: DedupKey) -> bool {
+        if slot < self.lowest_slot() {
+            // cannot tell if it was seen before - drop it
+            trace!(
+                "update for slot {} is outside dedup window - dropping",
+                slot
+            );
+            return false;
+        }
+
+        if !self.seen.insert(key.clone()) {
+            return false;
+        }
+        self.keys_by_slot.entry(slot).or_default().push(key);
+        self.highest_slot = self.highest_slot.max(slot);
+
+        self.prune();
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    const fn lowest_slot(&self) -> Slot {
+        self.highest_slot.saturating_sub(self.config.window_slots)
+    }
+
+    fn prune(&mut self) {
+        let lowest_slot = self.lowest_slot();
+        while let Some(oldest) = self.keys_by_slot.first_entry() {
+            let within_limit = self.seen.len() <= self.config.max_entries;
+            // the highest slot may exceed the limit on its own
+            if *oldest.key() >= self.highest_slot || (*oldest.key() >= lowest_slot && within_limit)
+            {
+                break;
+            }
+            for key in oldest.remove() {
+                self.seen.remove(&key);
+            }
+        }
+    }
+}
+
+/// use streams created by ``create_geyser_reconnecting_stream``;
+/// emits each update (account, transaction, slot, ...) once, the first arrival wins
+///
+/// in contrast to ``create_multiplexed_stream`` this does not require monotonic slots
+pub fn create_deduplicated_stream(
+    grpc_source_streams: Vec<impl Stream<Item = Message>>,
+    dedup_config: DedupConfig,
+) -> impl Stream<Item = Message> {
+    if grpc_source_streams.is_empty() {
+        panic!("Must have at least one grpc source");
+    }
+
+    info!(
+        "Starting dedup multiplexer with {} sources",
+        grpc_source_streams.len()
+    );
+
+    deduplicate_geyser_updates(merge_tagged(grpc_source_streams), dedup_config)
+}
+
+/// spawns one autoconnect task per source and emits each update once, the first arrival wins
+///
+/// returns the deduplicated receiver; all tasks shut down on exit signal or when the receiver is dropped
+pub fn create_deduplicated_task(
+    grpc_sources: Vec<GrpcSourceConfig>,
+    subscribe_filter: SubscribeRequest,
+    dedup_config: DedupConfig,
+    exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, mpsc::Receiver<Message>) {
+    let source_streams = spawn_source_tasks(grpc_sources, subscribe_filter, &exit_notify);
+
+    spawn_stream_forwarder(
+        create_deduplicated_stream(source_streams, dedup_config),
+        exit_notify,
+    )
+}
+
+fn deduplicate_geyser_updates(
+    merged_stream: impl Stream<Item = TaggedMessage>,
+    dedup_config: DedupConfig,
+) -> impl Stream<Item = Message> {
+    let mut dedup_window = DedupWindow::new(dedup_config);
+    stream! {
+        for await TaggedMessage {stream_idx, payload} in merged_stream {
+            match payload {
+                Message::GeyserSubscribeUpdate(update) => {
+                    if let Some((slot, key)) = dedup_key(&update) {
+                        if dedup_window.insert(slot, key) {
+                            yield Message::GeyserSubscribeUpdate(update);
+                        }
+                    }
+                }
+                Message::Connecting(attempt) => {
+                    if attempt > 1 {
+                        warn!("Stream-{} performs reconnect attempt {}", stream_idx, attempt);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account_key(pubkey: u8, write_version: u64) -> DedupKey {
+        DedupKey::Account(vec![pubkey; 32], write_version)
+    }
+
+    #[test]
+    fn test_first_arrival_wins() {
+        let mut window = DedupWindow::new(DedupConfig::default());
+        assert!(window.insert(100, account_key(1, 1000)));
+        assert!(!window.insert(100, account_key(1, 1000)));
+        // many updates per slot
+        assert!(window.insert(100, account_key(1, 1001)));
+        assert!(window.insert(100, account_key(2, 1000)));
+        assert_eq!(window.len(), 3);
+    }
+
+    #[test]
+    fn test_prune_by_slot() {
+        let mut window = DedupWindow::new(DedupConfig {
+            window_slots: 10,
+            max_entries: 1000,
+        });
+        assert!(window.insert(100, DedupKey::Transaction(vec![1; 64])));
+        assert!(window.insert(111, DedupKey::Transaction(vec![2; 64])));
+        assert_eq!(window.len(), 1);
+        // outside of window
+        assert!(!window.insert(100, DedupKey::Transaction(vec![1; 64])));
+    }
+
+    #[test]
+    fn test_prune_by_size() {
+        let mut window = DedupWindow::new(DedupConfig {
+            window_slots: 100,
+            max_entries: 2,
+        });
+        assert!(window.insert(100, DedupKey::Slot(100, 1)));
+        assert!(window.insert(101, DedupKey::Slot(101, 1)));
+        assert!(window.insert(102, DedupKey::Slot(102, 1)));
+        assert_eq!(window.len(), 2);
+        assert!(!window.insert(102, DedupKey::Slot(102, 1)));
+    }
+
+    #[test]
+    fn test_prune_by_size_keeps_highest_slot() {
+        let mut window = DedupWindow::new(DedupConfig {
+            window_slots: 100,
+            max_entries: 2,
+        });
+        assert!(window.insert(100, DedupKey::Slot(100, 1)));
+        for pubkey in 0..5 {
+            assert!(window.insert(101, account_key(pubkey, 1)));
+        }
+        assert_eq!(window.len(), 5);
+        for pubkey in 0..5 {
+            assert!(!window.insert(101, account_key(pubkey, 1)));
+        }
+        // the next slot prunes the previous one
+        assert!(window.insert(102, DedupKey::Slot(102, 1)));
+        assert_eq!(window.len(), 1);
+    }
+}
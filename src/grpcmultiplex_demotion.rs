@@ -0,0 +1,339 @@
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use tokio::select;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Instant};
+use tokio_stream::wrappers::ReceiverStream;
+use yellowstone_grpc_proto::geyser::SubscribeRequest;
+
+use crate::grpc_subscription_autoreconnect_tasks::{
+    create_geyser_autoconnection_task_with_log_tag, LogTag,
+};
+use crate::grpcmultiplex_fastestwins::{
+    extract_payload_from_geyser_updates, spawn_stream_forwarder, MultiplexConfig, MultiplexEvent,
+    MultiplexExtractor, TaggedMessage,
+};
+use crate::{GrpcSourceConfig, Message};
+
+#[derive(Clone, Debug)]
+pub struct DemotionConfig {
+    // source gets demoted if it lags at least this number of slots behind the tip ...
+    pub demote_lag_slots: u64,
+    // ... continuously for this duration
+    pub sustained_for: Duration,
+    // lagging state is cleared only if the lag drops to this number of slots (hysteresis)
+    pub recover_lag_slots: u64,
+    // demoted source stays disconnected for this duration, then gets re-probed
+    pub cooldown: Duration,
+}
+
+impl Default for DemotionConfig {
+    fn default() -> Self {
+        DemotionConfig {
+            demote_lag_slots: 2,
+            sustained_for: Duration::from_secs(30),
+            recover_lag_slots: 1,
+            cooldown: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SourceState {
+    Active { lagging_since: Option<Instant> },
+    Demoted { until: Instant },
+}
+
+#[derive(Debug, PartialEq)]
+enum SourceAction {
+    Demote,
+    Reprobe,
+}
+
+impl SourceState {
+    fn next_action(
+        &mut self,
+        slot_lag: Option<u64>,
+        now: Instant,
+        config: &DemotionConfig,
+        can_demote: bool,
+    ) -> Option<SourceAction> {
+        match self {
+            SourceState::Active { lagging_since } => {
+                let slot_lag = slot_lag?;
+                if slot_lag >= config.demote_lag_slots {
+                    let since = *lagging_since.get_or_insert(now);
+                    if can_demote && now.duration_since(since) >= config.sustained_for {
+                        *self = SourceState::Demoted {
+                            until: now + config.cooldown,
+                        };
+                        return Some(SourceAction::Demote);
+                    }
+                } else if slot_lag <= config.recover_lag_slots {
+                    *lagging_since = None;
+                }
+                None
+            }
+            SourceState::Demoted { until } => {
+                if now >= *until {
+                    *self = SourceState::Active {
+                        lagging_since: None,
+                    };
+                    return Some(SourceAction::Reprobe);
+                }
+                None
+            }
+        }
+    }
+}
+
+struct SupervisedSource {
+    grpc_source: GrpcSourceConfig,
+    // None while demoted
+    exit_tx: Option<broadcast::Sender<()>>,
+    state: SourceState,
+}
+
+/// same as ``create_multiplexed_event_task``; sources which lag behind the tip for a sustained period
+/// get disconnected and re-probed after a cooldown; the last active source never gets demoted
+///
+/// note: a demoted source keeps its last connection status, i.e. it does not count as down for ``MultiplexEvent::AllSourcesDown``
+pub fn create_multiplexed_task_with_demotion<E>(
+    grpc_sources: Vec<GrpcSourceConfig>,
+    subscribe_filter: SubscribeRequest,
+    extractor: E,
+    multiplex_config: MultiplexConfig,
+    demotion_config: DemotionConfig,
+    mut exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, mpsc::Receiver<MultiplexEvent<E::Target>>)
+where
+    E: MultiplexExtractor + Send + 'static,
+    E::Target: Send + 'static,
+{
+    if grpc_sources.is_empty() {
+        panic!("Must have at least one grpc source");
+    }
+
+    // stats are used to track the lag of the sources
+    let multiplex_stats = multiplex_config.multiplex_stats.clone().unwrap_or_default();
+    let multiplex_config = MultiplexConfig {
+        multiplex_stats: Some(multiplex_stats.clone()),
+        ..multiplex_config
+    };
+
+    let num_sources = grpc_sources.len();
+    let (merged_tx, merged_rx) = mpsc::channel::<TaggedMessage>(num_sources);
+
+    let mut sources: Vec<SupervisedSource> = grpc_sources
+        .into_iter()
+        .enumerate()
+        .map(|(idx, grpc_source)| SupervisedSource {
+            exit_tx: Some(spawn_source_task(
+                idx,
+                grpc_source.clone(),
+                subscribe_filter.clone(),
+                merged_tx.clone(),
+            )),
+            grpc_source,
+            state: SourceState::Active {
+                lagging_since: None,
+            },
+        })
+        .collect();
+
+    let (jh_multiplexer, multiplexed_rx) = spawn_stream_forwarder(
+        extract_payload_from_geyser_updates(
+            ReceiverStream::new(merged_rx),
+            num_sources,
+            extractor,
+            multiplex_config,
+        ),
+        exit_notify.resubscribe(),
+    );
+
+    let _jh_supervisor = tokio::spawn(async move {
+        let mut check_interval = interval(Duration::from_secs(1));
+        loop {
+            select! {
+                _ = exit_notify.recv() => {
+                    debug!("exit on signal - shutting down source supervisor");
+                    break;
+                }
+                _ = check_interval.tick() => {
+                    if merged_tx.is_closed() {
+                        debug!("multiplexer closed - shutting down source supervisor");
+                        break;
+                    }
+
+                    let now = Instant::now();
+                    let mut active_sources = sources
+                        .iter()
+                        .filter(|source| source.exit_tx.is_some())
+                        .count();
+                    for (idx, source) in sources.iter_mut().enumerate() {
+                        let slot_lag = multiplex_stats.slot_lag(idx);
+                        match source.state.next_action(slot_lag, now, &demotion_config, active_sources > 1) {
+                            Some(SourceAction::Demote) => {
+                                warn!(
+                                    "Stream-{} lags {:?} slots behind tip - disconnect for {:?}: source={}",
+                                    idx, slot_lag, demotion_config.cooldown, source.grpc_source
+                                );
+                                if let Some(exit_tx) = source.exit_tx.take() {
+                                    let _ = exit_tx.send(());
+                                }
+                                active_sources -= 1;
+                            }
+                            Some(SourceAction::Reprobe) => {
+                                info!("Stream-{} cooldown over - re-probing: source={}", idx, source.grpc_source);
+                                multiplex_stats.reset_source(idx);
+                                source.exit_tx = Some(spawn_source_task(
+                                    idx,
+                                    source.grpc_source.clone(),
+                                    subscribe_filter.clone(),
+                                    merged_tx.clone(),
+                                ));
+                                active_sources += 1;
+                            }
+                            None => {}
+                        }
+                    }
+                }
+            }
+        }
+
+        for source in sources {
+            if let Some(exit_tx) = source.exit_tx {
+                let _ = exit_tx.send(());
+            }
+        }
+    });
+
+    (jh_multiplexer, multiplexed_rx)
+}
+
+/// spawn autoconnect task which can be stopped individually using the returned sender
+fn spawn_source_task(
+    idx: usize,
+    grpc_source: GrpcSourceConfig,
+    subscribe_filter: SubscribeRequest,
+    merged_tx: mpsc::Sender<TaggedMessage>,
+) -> broadcast::Sender<()> {
+    let (exit_tx, exit_rx) = broadcast::channel(1);
+    let (source_tx, mut source_rx) = mpsc::channel::<Message>(1);
+
+    let _jh_source = create_geyser_autoconnection_task_with_log_tag(
+        // source status events are derived from them
+        grpc_source.with_connecting_messages(),
+        subscribe_filter,
+        source_tx,
+        exit_rx,
+        None,
+        &Some(LogTag(format!("source-{}", idx))),
+    );
+
+    tokio::spawn(async move {
+        while let Some(payload) = source_rx.recv().await {
+            let tagged = TaggedMessage {
+                stream_idx: idx,
+                payload,
+            };
+            if merged_tx.send(tagged).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    exit_tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> DemotionConfig {
+        DemotionConfig {
+            demote_lag_slots: 2,
+            sustained_for: Duration::from_secs(10),
+            recover_lag_slots: 0,
+            cooldown: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_demote_after_sustained_lag() {
+        let config = config();
+        let start = Instant::now();
+        let mut state = SourceState::Active {
+            lagging_since: None,
+        };
+
+        assert_eq!(state.next_action(Some(3), start, &config, true), None);
+        assert_eq!(
+            state.next_action(Some(2), start + Duration::from_secs(5), &config, true),
+            None
+        );
+        assert_eq!(
+            state.next_action(Some(2), start + Duration::from_secs(10), &config, true),
+            Some(SourceAction::Demote)
+        );
+        assert_eq!(
+            state.next_action(None, start + Duration::from_secs(30), &config, true),
+            None
+        );
+        assert_eq!(
+            state.next_action(None, start + Duration::from_secs(70), &config, true),
+            Some(SourceAction::Reprobe)
+        );
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let config = config();
+        let start = Instant::now();
+        let mut state = SourceState::Active {
+            lagging_since: None,
+        };
+
+        assert_eq!(state.next_action(Some(2), start, &config, true), None);
+        // lag of 1 is below demote threshold but above recover threshold - keep lagging state
+        assert_eq!(
+            state.next_action(Some(1), start + Duration::from_secs(5), &config, true),
+            None
+        );
+        assert_eq!(
+            state.next_action(Some(2), start + Duration::from_secs(10), &config, true),
+            Some(SourceAction::Demote)
+        );
+
+        let mut state = SourceState::Active {
+            lagging_since: None,
+        };
+        assert_eq!(state.next_action(Some(2), start, &config, true), None);
+        // caught up - reset
+        assert_eq!(
+            state.next_action(Some(0), start + Duration::from_secs(5), &config, true),
+            None
+        );
+        assert_eq!(
+            state.next_action(Some(2), start + Duration::from_secs(10), &config, true),
+            None
+        );
+    }
+
+    #[test]
+    fn test_never_demote_last_source() {
+        let config = config();
+        let start = Instant::now();
+        let mut state = SourceState::Active {
+            lagging_since: None,
+        };
+
+        assert_eq!(state.next_action(Some(5), start, &config, false), None);
+        assert_eq!(
+            state.next_action(Some(5), start + Duration::from_secs(20), &config, false),
+            None
+        );
+    }
+}
@@ -1,11 +1,25 @@
-use crate::Message;
+use crate::grpc_subscription_autoreconnect_tasks::{
+    create_geyser_autoconnection_task_with_log_tag, LogTag,
+};
+use crate::grpcmultiplex_sanity::{SlotSanityCheck, SlotSanityConfig, SlotVerdict};
+use crate::grpcmultiplex_stats::MultiplexStats;
 use crate::Message::GeyserSubscribeUpdate;
+use crate::{Attempt, GrpcSourceConfig, Message};
 use async_stream::stream;
+use futures::future::ready;
 use futures::{Stream, StreamExt};
-use log::{info, warn};
+use log::{debug, info, warn};
 use merge_streams::MergeStreams;
 use solana_clock::Slot;
-use yellowstone_grpc_proto::geyser::SubscribeUpdate;
+use std::collections::BTreeMap;
+use std::pin::pin;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Instant, MissedTickBehavior};
+use tokio_stream::wrappers::ReceiverStream;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{SubscribeRequest, SubscribeUpdate};
 
 pub trait FromYellowstoneExtractor {
     // Target is something like ProducedBlock
@@ -13,15 +27,328 @@ pub trait FromYellowstoneExtractor {
     fn map_yellowstone_update(&self, update: SubscribeUpdate) -> Option<(Slot, Self::Target)>;
 }
 
+/// ordering key of multiplexed items; the multiplexer emits items with strictly increasing keys
+pub trait OrderingKey: Ord + Copy + Send {
+    // slot the item belongs to; used for gap detection and stats
+    fn slot(self) -> Slot;
+}
+
+impl OrderingKey for Slot {
+    fn slot(self) -> Slot {
+        self
+    }
+}
+
+// e.g. (slot, transaction index) to emit every transaction of a block
+impl<T: Ord + Copy + Send> OrderingKey for (Slot, T) {
+    fn slot(self) -> Slot {
+        self.0
+    }
+}
+
+/// general form of ``FromYellowstoneExtractor``: may emit any number of items per update
+/// and gets the index of the source which delivered the update
+///
+/// implemented for every ``FromYellowstoneExtractor`` and for closures wrapped with ``extractor_fn``;
+/// supported by the fastest-wins multiplexers only, the quorum and fork-aware multiplexers work per slot
+pub trait MultiplexExtractor {
+    type Key: OrderingKey;
+    type Target;
+    type Items: IntoIterator<Item = (Self::Key, Self::Target)>;
+    fn extract(&self, source_idx: usize, update: SubscribeUpdate) -> Self::Items;
+}
+
+impl<E: FromYellowstoneExtractor> MultiplexExtractor for E {
+    type Key = Slot;
+    type Target = E::Target;
+    type Items = Option<(Slot, E::Target)>;
+    fn extract(&self, _source_idx: usize, update: SubscribeUpdate) -> Self::Items {
+        self.map_yellowstone_update(update)
+    }
+}
+
+/// see ``extractor_fn``
+pub struct ExtractorFn<F>(F);
+
+/// use a closure `|source_idx, update| -> impl IntoIterator<Item = (key, item)>` as extractor
+pub const fn extractor_fn<F, I, K, T>(f: F) -> ExtractorFn<F>
+where
+    F: Fn(usize, SubscribeUpdate) -> I,
+    I: IntoIterator<Item = (K, T)>,
+    K: OrderingKey,
+{
+    ExtractorFn(f)
+}
+
+impl<F, I, K, T> MultiplexExtractor for ExtractorFn<F>
+where
+    F: Fn(usize, SubscribeUpdate) -> I,
+    I: IntoIterator<Item = (K, T)>,
+    K: OrderingKey,
+{
+    type Key = K;
+    type Target = T;
+    type Items = I;
+    fn extract(&self, source_idx: usize, update: SubscribeUpdate) -> I {
+        (self.0)(source_idx, update)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MultiplexEvent<T> {
+    Item(T),
+    // the chain of emitted blocks is broken: blocks after slot `from` up to slot `to` (inclusive) were not emitted;
+    // note: this is detected from parent slot information (block, block meta, slot updates) only
+    Gap { from: Slot, to: Slot },
+    // source started a (re)connect attempt
+    SourceConnecting { idx: usize, attempt: Attempt },
+    // source delivered its first update after connecting
+    SourceReady { idx: usize },
+    // source delivered an implausible slot (or set a tip which was not confirmed); its updates are ignored for a while
+    SourceQuarantined { idx: usize, slot: Slot },
+    // the tip was set by a quarantined source; emission continues at slot `to`
+    TipReset { from: Slot, to: Slot },
+    // no source delivers updates; not emitted during startup
+    AllSourcesDown,
+    // a source delivers updates again after ``AllSourcesDown``
+    Recovered,
+}
+
+/// connection status of the sources as seen by the multiplexer
+struct SourceStatusTracker {
+    ready: Vec<bool>,
+    any_ready_before: bool,
+    all_down: bool,
+}
+
+impl SourceStatusTracker {
+    fn new(num_sources: usize) -> Self {
+        SourceStatusTracker {
+            ready: vec![false; num_sources],
+            any_ready_before: false,
+            all_down: false,
+        }
+    }
+
+    fn on_connecting<T>(&mut self, source_idx: usize, attempt: Attempt) -> Vec<MultiplexEvent<T>> {
+        let mut events = vec![MultiplexEvent::SourceConnecting {
+            idx: source_idx,
+            attempt,
+        }];
+        if let Some(ready) = self.ready.get_mut(source_idx) {
+            *ready = false;
+        }
+        if self.any_ready_before && !self.all_down && !self.ready.contains(&true) {
+            warn!("all sources are down");
+            self.all_down = true;
+            events.push(MultiplexEvent::AllSourcesDown);
+        }
+        events
+    }
+
+    fn on_update<T>(&mut self, source_idx: usize) -> Vec<MultiplexEvent<T>> {
+        if self.ready.len() <= source_idx {
+            self.ready.resize(source_idx + 1, false);
+        }
+        if self.ready[source_idx] {
+            return vec![];
+        }
+
+        self.ready[source_idx] = true;
+        self.any_ready_before = true;
+        let mut events = vec![MultiplexEvent::SourceReady { idx: source_idx }];
+        if self.all_down {
+            info!("Stream-{} recovered from all sources down", source_idx);
+            self.all_down = false;
+            events.push(MultiplexEvent::Recovered);
+        }
+        events
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MultiplexConfig {
+    // record per-source win rate and lag
+    pub multiplex_stats: Option<MultiplexStats>,
+    // hold items to emit them in order even if the sources deliver slightly out of order; adds latency
+    pub reorder_window: Option<ReorderWindow>,
+    // protect the tip against bogus far-future slots
+    pub slot_sanity: Option<SlotSanityConfig>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ReorderWindow {
+    // hold items for up to this duration after arrival
+    Duration(Duration),
+    // hold items until an item this number of slots higher arrived
+    Slots(u64),
+}
+
+struct PendingItem<T> {
+    item: T,
+    parent_slot: Option<Slot>,
+    source_idx: usize,
+    arrival: Instant,
+}
+
+/// items which were not emitted yet, ordered by key
+struct ReorderBuffer<K, T> {
+    window: ReorderWindow,
+    pending: BTreeMap<K, PendingItem<T>>,
+    highest_slot: Slot,
+}
+
+impl<K: OrderingKey, T> ReorderBuffer<K, T> {
+    const fn new(window: ReorderWindow) -> Self {
+        ReorderBuffer {
+            window,
+            pending: BTreeMap::new(),
+            highest_slot: 0,
+        }
+    }
+
+    /// false if an item with the same key is pending already
+    fn insert(
+        &mut self,
+        key: K,
+        item: T,
+        parent_slot: Option<Slot>,
+        source_idx: usize,
+        now: Instant,
+    ) -> bool {
+        if self.pending.contains_key(&key) {
+            return false;
+        }
+        self.highest_slot = self.highest_slot.max(key.slot());
+        self.pending.insert(
+            key,
+            PendingItem {
+                item,
+                parent_slot,
+                source_idx,
+                arrival: now,
+            },
+        );
+        true
+    }
+
+    /// lowest pending item if its time in the window is over
+    fn pop_ready(&mut self, now: Instant) -> Option<(K, PendingItem<T>)> {
+        let (key, pending) = self.pending.first_key_value()?;
+        let ready = match self.window {
+            ReorderWindow::Duration(duration) => now.duration_since(pending.arrival) >= duration,
+            ReorderWindow::Slots(slots) => key.slot().saturating_add(slots) <= self.highest_slot,
+        };
+        if ready {
+            self.pending.pop_first()
+        } else {
+            None
+        }
+    }
+
+    fn pop_first(&mut self) -> Option<(K, PendingItem<T>)> {
+        self.pending.pop_first()
+    }
+
+    fn remove_source(&mut self, source_idx: usize) {
+        self.pending
+            .retain(|_, pending| pending.source_idx != source_idx);
+        self.highest_slot = self
+            .pending
+            .last_key_value()
+            .map(|(key, _)| key.slot())
+            .unwrap_or_default();
+    }
+}
+
+/// keeps track of the last emitted item and detects gaps
+struct Emitter<K> {
+    tip: Option<K>,
+    tip_slot: Slot,
+}
+
+impl<K: OrderingKey> Emitter<K> {
+    const fn new() -> Self {
+        Emitter {
+            tip: None,
+            tip_slot: 0,
+        }
+    }
+
+    fn emit<T>(
+        &mut self,
+        key: K,
+        item: T,
+        parent_slot: Option<Slot>,
+        events: &mut Vec<MultiplexEvent<T>>,
+    ) {
+        let slot = key.slot();
+        if slot > self.tip_slot {
+            if let Some(parent_slot) = parent_slot {
+                if self.tip_slot > 0 && parent_slot > self.tip_slot {
+                    debug!(
+                        "gap detected: last emitted slot {}, parent of slot {} is {}",
+                        self.tip_slot, slot, parent_slot
+                    );
+                    events.push(MultiplexEvent::Gap {
+                        from: self.tip_slot,
+                        to: parent_slot,
+                    });
+                }
+            }
+            self.tip_slot = slot;
+        }
+        self.tip = self.tip.max(Some(key));
+        events.push(MultiplexEvent::Item(item));
+    }
+}
+
 /// use streams created by ``create_geyser_reconnecting_stream``
 /// this is agnostic to the type of the stream
-/// CAUTION: do not try to use with commitment level "processed" as this will form trees (forks) and not a sequence
+/// CAUTION: do not try to use with commitment level "processed" as this will form trees (forks) and not a sequence;
+/// use ``create_fork_aware_multiplexed_stream`` instead
 pub fn create_multiplexed_stream<E>(
     grpc_source_streams: Vec<impl Stream<Item = Message>>,
     extractor: E,
 ) -> impl Stream<Item = E::Target>
 where
-    E: FromYellowstoneExtractor,
+    E: MultiplexExtractor,
+{
+    only_items(create_multiplexed_event_stream(
+        grpc_source_streams,
+        extractor,
+        MultiplexConfig::default(),
+    ))
+}
+
+/// same as ``create_multiplexed_stream``; records per-source win rate and lag in `multiplex_stats`
+pub fn create_multiplexed_stream_with_stats<E>(
+    grpc_source_streams: Vec<impl Stream<Item = Message>>,
+    extractor: E,
+    multiplex_stats: MultiplexStats,
+) -> impl Stream<Item = E::Target>
+where
+    E: MultiplexExtractor,
+{
+    only_items(create_multiplexed_event_stream(
+        grpc_source_streams,
+        extractor,
+        MultiplexConfig {
+            multiplex_stats: Some(multiplex_stats),
+            ..Default::default()
+        },
+    ))
+}
+
+/// same as ``create_multiplexed_stream`` but emits ``MultiplexEvent``s which inform about gaps in the emitted chain
+/// and the connection status of the sources
+pub fn create_multiplexed_event_stream<E>(
+    grpc_source_streams: Vec<impl Stream<Item = Message>>,
+    extractor: E,
+    multiplex_config: MultiplexConfig,
+) -> impl Stream<Item = MultiplexEvent<E::Target>>
+where
+    E: MultiplexExtractor,
 {
     if grpc_source_streams.is_empty() {
         panic!("Must have at least one grpc source");
@@ -32,6 +359,142 @@ where
         grpc_source_streams.len()
     );
 
+    let num_sources = grpc_source_streams.len();
+    extract_payload_from_geyser_updates(
+        merge_tagged(grpc_source_streams),
+        num_sources,
+        extractor,
+        multiplex_config,
+    )
+}
+
+/// spawns one autoconnect task per source and multiplexes their updates using fastest-wins strategy;
+/// same as ``create_multiplexed_stream`` but using the maintained task API
+///
+/// returns the deduplicated receiver; all tasks shut down on exit signal or when the receiver is dropped
+/// CAUTION: do not try to use with commitment level "processed" as this will form trees (forks) and not a sequence
+pub fn create_multiplexed_task<E>(
+    grpc_sources: Vec<GrpcSourceConfig>,
+    subscribe_filter: SubscribeRequest,
+    extractor: E,
+    exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, mpsc::Receiver<E::Target>)
+where
+    E: MultiplexExtractor + Send + 'static,
+    E::Target: Send + 'static,
+{
+    let source_streams = spawn_source_tasks(grpc_sources, subscribe_filter, &exit_notify);
+
+    spawn_stream_forwarder(
+        create_multiplexed_stream(source_streams, extractor),
+        exit_notify,
+    )
+}
+
+/// same as ``create_multiplexed_task``; records per-source win rate and lag in `multiplex_stats`
+///
+/// the source index used in the stats is the index in `grpc_sources`
+pub fn create_multiplexed_task_with_stats<E>(
+    grpc_sources: Vec<GrpcSourceConfig>,
+    subscribe_filter: SubscribeRequest,
+    extractor: E,
+    exit_notify: broadcast::Receiver<()>,
+    multiplex_stats: MultiplexStats,
+) -> (JoinHandle<()>, mpsc::Receiver<E::Target>)
+where
+    E: MultiplexExtractor + Send + 'static,
+    E::Target: Send + 'static,
+{
+    let source_streams = spawn_source_tasks(grpc_sources, subscribe_filter, &exit_notify);
+
+    spawn_stream_forwarder(
+        create_multiplexed_stream_with_stats(source_streams, extractor, multiplex_stats),
+        exit_notify,
+    )
+}
+
+/// spawns one autoconnect task per source; see ``create_multiplexed_event_stream``
+pub fn create_multiplexed_event_task<E>(
+    grpc_sources: Vec<GrpcSourceConfig>,
+    subscribe_filter: SubscribeRequest,
+    extractor: E,
+    multiplex_config: MultiplexConfig,
+    exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, mpsc::Receiver<MultiplexEvent<E::Target>>)
+where
+    E: MultiplexExtractor + Send + 'static,
+    E::Target: Send + 'static,
+{
+    let source_streams = spawn_source_tasks(grpc_sources, subscribe_filter, &exit_notify);
+
+    spawn_stream_forwarder(
+        create_multiplexed_event_stream(source_streams, extractor, multiplex_config),
+        exit_notify,
+    )
+}
+
+/// spawn one autoconnect task per source; the tasks shut down on exit signal or when the returned streams are dropped
+pub(crate) fn spawn_source_tasks(
+    grpc_sources: Vec<GrpcSourceConfig>,
+    subscribe_filter: SubscribeRequest,
+    exit_notify: &broadcast::Receiver<()>,
+) -> Vec<ReceiverStream<Message>> {
+    if grpc_sources.is_empty() {
+        panic!("Must have at least one grpc source");
+    }
+
+    let mut source_streams = Vec::with_capacity(grpc_sources.len());
+    for (idx, grpc_source) in grpc_sources.into_iter().enumerate() {
+        let (source_tx, source_rx) = mpsc::channel::<Message>(1);
+        let _jh_source = create_geyser_autoconnection_task_with_log_tag(
+            // source status events are derived from them
+            grpc_source.with_connecting_messages(),
+            subscribe_filter.clone(),
+            source_tx,
+            exit_notify.resubscribe(),
+            None,
+            &Some(LogTag(format!("source-{}", idx))),
+        );
+        source_streams.push(ReceiverStream::new(source_rx));
+    }
+    source_streams
+}
+
+/// drive the stream in a task and forward its items to the returned mpsc channel
+pub(crate) fn spawn_stream_forwarder<T: Send + 'static>(
+    stream: impl Stream<Item = T> + Send + 'static,
+    mut exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, mpsc::Receiver<T>) {
+    let (multiplexed_tx, multiplexed_rx) = mpsc::channel::<T>(1);
+
+    let jh_multiplexer = tokio::spawn(async move {
+        let mut multiplexed_stream = pin!(stream);
+        loop {
+            tokio::select! {
+                next = multiplexed_stream.next() => {
+                    let Some(item) = next else {
+                        debug!("all source streams closed - shutting down multiplexer");
+                        break;
+                    };
+                    if multiplexed_tx.send(item).await.is_err() {
+                        debug!("multiplexer downstream closed - shutting down multiplexer");
+                        break;
+                    }
+                }
+                _ = exit_notify.recv() => {
+                    debug!("exit on signal - shutting down multiplexer");
+                    break;
+                }
+            }
+        }
+    });
+
+    (jh_multiplexer, multiplexed_rx)
+}
+
+pub(crate) fn merge_tagged(
+    grpc_source_streams: Vec<impl Stream<Item = Message>>,
+) -> impl Stream<Item = TaggedMessage> {
     let mut streams = vec![];
     for (idx, grpc_stream) in grpc_source_streams.into_iter().enumerate() {
         let tagged = grpc_stream.map(move |msg| TaggedMessage {
@@ -41,42 +504,487 @@ where
         streams.push(Box::pin(tagged));
     }
 
-    let merged_streams = streams.merge();
-
-    extract_payload_from_geyser_updates(merged_streams, extractor)
+    streams.merge()
 }
 
-struct TaggedMessage {
+pub(crate) struct TaggedMessage {
     pub stream_idx: usize,
     pub payload: Message,
 }
 
-fn extract_payload_from_geyser_updates<E>(
+/// get (slot, parent) from updates which carry parent information (block, block meta, slot)
+pub(crate) fn parent_link(update: &SubscribeUpdate) -> Option<(Slot, Slot)> {
+    match update.update_oneof.as_ref()? {
+        UpdateOneof::Block(block) => Some((block.slot, block.parent_slot)),
+        UpdateOneof::BlockMeta(meta) => Some((meta.slot, meta.parent_slot)),
+        UpdateOneof::Slot(slot) => Some((slot.slot, slot.parent?)),
+        _ => None,
+    }
+}
+
+fn only_items<T>(events: impl Stream<Item = MultiplexEvent<T>>) -> impl Stream<Item = T> {
+    events.filter_map(|event| {
+        ready(match event {
+            MultiplexEvent::Item(item) => Some(item),
+            _ => None,
+        })
+    })
+}
+
+pub(crate) fn extract_payload_from_geyser_updates<E>(
     merged_stream: impl Stream<Item = TaggedMessage>,
+    num_sources: usize,
     extractor: E,
-) -> impl Stream<Item = E::Target>
+    multiplex_config: MultiplexConfig,
+) -> impl Stream<Item = MultiplexEvent<E::Target>>
 where
-    E: FromYellowstoneExtractor,
+    E: MultiplexExtractor,
 {
-    let mut tip: Slot = 0;
+    let MultiplexConfig {
+        multiplex_stats,
+        reorder_window,
+        slot_sanity,
+    } = multiplex_config;
+    let mut slot_sanity = slot_sanity.map(|config| SlotSanityCheck::new(config, num_sources));
+    let mut emitter: Emitter<E::Key> = Emitter::new();
+    let mut reorder_buffer = reorder_window.map(ReorderBuffer::new);
+    // highest slot accepted for emission
+    let mut accepted_slot: Slot = 0;
+    let mut source_status = SourceStatusTracker::new(num_sources);
+    // a time-based window must be flushed even if no updates arrive
+    let flush_interval = match reorder_window {
+        Some(ReorderWindow::Duration(duration)) => {
+            Some((duration / 4).max(Duration::from_millis(10)))
+        }
+        _ => None,
+    };
     stream! {
-        for await TaggedMessage {stream_idx, payload} in merged_stream {
-            match payload {
-                GeyserSubscribeUpdate(update) => {
-                    // take only the update messages we want
-                    if let Some((proposed_slot, block)) = extractor.map_yellowstone_update(*update) {
-                        if proposed_slot > tip {
-                            tip = proposed_slot;
-                            yield block;
+        let mut merged_stream = pin!(merged_stream);
+        let mut flush_check = flush_interval.map(|flush_interval| {
+            let mut flush_check = interval(flush_interval);
+            flush_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            flush_check
+        });
+        loop {
+            let events = tokio::select! {
+                next = merged_stream.next() => {
+                    match next {
+                        Some(TaggedMessage { stream_idx, payload: GeyserSubscribeUpdate(update) }) => {
+                            let parent_link = parent_link(&update);
+                            // all items of one update are compared against the tip before the update
+                            let mut update_tip = emitter.tip;
+                            let mut late_slot = None;
+                            let mut events = source_status.on_update(stream_idx);
+                            let now = Instant::now();
+                            // take only the update messages we want
+                            for (key, item) in extractor.extract(stream_idx, *update) {
+                                let proposed_slot = key.slot();
+                                let parent_slot = parent_link
+                                    .filter(|(slot, _)| *slot == proposed_slot)
+                                    .map(|(_, parent_slot)| parent_slot);
+
+                                if let Some(slot_sanity) = &mut slot_sanity {
+                                    match slot_sanity.check(stream_idx, proposed_slot, now) {
+                                        SlotVerdict::Accept => {}
+                                        SlotVerdict::Ignore => continue,
+                                        SlotVerdict::Quarantine => {
+                                            if let Some(stats) = &multiplex_stats {
+                                                stats.reset_source(stream_idx);
+                                            }
+                                            events.push(MultiplexEvent::SourceQuarantined { idx: stream_idx, slot: proposed_slot });
+                                            continue;
+                                        }
+                                        SlotVerdict::ResetTip { poisoned_source, from } => {
+                                            emitter = Emitter::new();
+                                            update_tip = None;
+                                            accepted_slot = 0;
+                                            if let Some(reorder_buffer) = &mut reorder_buffer {
+                                                reorder_buffer.remove_source(poisoned_source);
+                                            }
+                                            if let Some(stats) = &multiplex_stats {
+                                                stats.reset_source(poisoned_source);
+                                                stats.reset_tip(proposed_slot);
+                                            }
+                                            events.push(MultiplexEvent::SourceQuarantined { idx: poisoned_source, slot: from });
+                                            events.push(MultiplexEvent::TipReset { from, to: proposed_slot });
+                                        }
+                                    }
+                                }
+
+                                let accepted = if update_tip.is_some_and(|update_tip| key <= update_tip) {
+                                    false
+                                } else if let Some(reorder_buffer) = &mut reorder_buffer {
+                                    reorder_buffer.insert(key, item, parent_slot, stream_idx, now)
+                                } else {
+                                    emitter.emit(key, item, parent_slot, &mut events);
+                                    true
+                                };
+
+                                if !accepted {
+                                    if late_slot != Some(proposed_slot) {
+                                        late_slot = Some(proposed_slot);
+                                        if let Some(stats) = &multiplex_stats {
+                                            stats.record_late_arrival(stream_idx, proposed_slot);
+                                        }
+                                    }
+                                    continue;
+                                }
+
+                                if proposed_slot > accepted_slot {
+                                    accepted_slot = proposed_slot;
+                                    if let Some(stats) = &multiplex_stats {
+                                        stats.record_win(stream_idx, proposed_slot);
+                                    }
+                                }
+                            }
+
+                            if let Some(reorder_buffer) = &mut reorder_buffer {
+                                while let Some((key, pending)) = reorder_buffer.pop_ready(now) {
+                                    emitter.emit(key, pending.item, pending.parent_slot, &mut events);
+                                }
+                            }
+                            events
+                        }
+                        Some(TaggedMessage { stream_idx, payload: Message::Connecting(attempt) }) => {
+                            if attempt > 1 {
+                                warn!("Stream-{} performs reconnect attempt {}", stream_idx, attempt);
+                            }
+                            source_status.on_connecting(stream_idx, attempt)
                         }
+                        None => break,
                     }
                 }
-                Message::Connecting(attempt) => {
-                    if attempt > 1 {
-                        warn!("Stream-{} performs reconnect attempt {}", stream_idx, attempt);
+                _ = async { flush_check.as_mut().expect("flush interval").tick().await }, if flush_check.is_some() => {
+                    let mut events = vec![];
+                    if let Some(reorder_buffer) = &mut reorder_buffer {
+                        let now = Instant::now();
+                        while let Some((key, pending)) = reorder_buffer.pop_ready(now) {
+                            emitter.emit(key, pending.item, pending.parent_slot, &mut events);
+                        }
                     }
+                    events
                 }
+            };
+
+            for event in events {
+                yield event;
+            }
+        }
+
+        // all sources closed - emit what is left in the window
+        if let Some(reorder_buffer) = &mut reorder_buffer {
+            let mut events = vec![];
+            while let Some((key, pending)) = reorder_buffer.pop_first() {
+                emitter.emit(key, pending.item, pending.parent_slot, &mut events);
+            }
+            for event in events {
+                yield event;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        mock_block_meta_update, mock_message, BlockMetaSlotExtractor, MockGeyserServer,
+        MockSubscription,
+    };
+
+    #[tokio::test]
+    async fn test_fastest_wins() {
+        let source = futures::stream::iter(vec![
+            mock_message(mock_block_meta_update(101, 100)),
+            mock_message(mock_block_meta_update(102, 101)),
+            mock_message(mock_block_meta_update(101, 100)),
+            mock_message(mock_block_meta_update(103, 102)),
+        ]);
+
+        let items = create_multiplexed_stream(vec![source], BlockMetaSlotExtractor)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(items, vec![101, 102, 103]);
+    }
+
+    #[tokio::test]
+    async fn test_gap_event() {
+        let source = futures::stream::iter(vec![
+            mock_message(mock_block_meta_update(101, 100)),
+            // slot 102 was skipped by the leader
+            mock_message(mock_block_meta_update(103, 101)),
+            // block 104 was lost
+            mock_message(mock_block_meta_update(105, 104)),
+        ]);
+
+        let events = create_multiplexed_event_stream(
+            vec![source],
+            BlockMetaSlotExtractor,
+            MultiplexConfig::default(),
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+        assert_eq!(
+            events,
+            vec![
+                MultiplexEvent::SourceReady { idx: 0 },
+                MultiplexEvent::Item(101),
+                MultiplexEvent::Item(103),
+                MultiplexEvent::Gap { from: 103, to: 104 },
+                MultiplexEvent::Item(105),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_source_status_events() {
+        let source_a = futures::stream::iter(vec![
+            Message::Connecting(1),
+            mock_message(mock_block_meta_update(101, 100)),
+            Message::Connecting(2),
+        ]);
+        let source_b = futures::stream::iter(vec![Message::Connecting(1)]);
+
+        let events = create_multiplexed_event_stream(
+            vec![source_a],
+            BlockMetaSlotExtractor,
+            MultiplexConfig::default(),
+        )
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(
+            events,
+            vec![
+                MultiplexEvent::SourceConnecting { idx: 0, attempt: 1 },
+                MultiplexEvent::SourceReady { idx: 0 },
+                MultiplexEvent::Item(101),
+                MultiplexEvent::SourceConnecting { idx: 0, attempt: 2 },
+                MultiplexEvent::AllSourcesDown,
+            ]
+        );
+
+        // no source was ready before - not reported as down during startup
+        let events = create_multiplexed_event_stream(
+            vec![source_b],
+            BlockMetaSlotExtractor,
+            MultiplexConfig::default(),
+        )
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(
+            events,
+            vec![MultiplexEvent::SourceConnecting { idx: 0, attempt: 1 }]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_source_status_events_from_tasks() {
+        let server = MockGeyserServer::start([
+            MockSubscription::new()
+                .emit(mock_block_meta_update(101, 100))
+                .close(),
+            MockSubscription::new().emit(mock_block_meta_update(102, 101)),
+        ])
+        .await
+        .unwrap();
+        let (exit_tx, exit_rx) = broadcast::channel(1);
+        let (jh_multiplexer, mut events_rx) = create_multiplexed_event_task(
+            vec![GrpcSourceConfig::new_simple(server.grpc_addr())],
+            SubscribeRequest::default(),
+            BlockMetaSlotExtractor,
+            MultiplexConfig::default(),
+            exit_rx,
+        );
+
+        let mut events = vec![];
+        while events.last() != Some(&MultiplexEvent::Item(102)) {
+            events.push(events_rx.recv().await.expect("multiplexer closed"));
+        }
+        assert_eq!(
+            events,
+            vec![
+                MultiplexEvent::SourceConnecting { idx: 0, attempt: 1 },
+                MultiplexEvent::SourceReady { idx: 0 },
+                MultiplexEvent::Item(101),
+                MultiplexEvent::SourceConnecting { idx: 0, attempt: 1 },
+                MultiplexEvent::AllSourcesDown,
+                MultiplexEvent::SourceReady { idx: 0 },
+                MultiplexEvent::Recovered,
+                MultiplexEvent::Item(102),
+            ]
+        );
+
+        exit_tx.send(()).unwrap();
+        jh_multiplexer.await.unwrap();
+    }
+
+    #[test]
+    fn test_recovered() {
+        let mut tracker = SourceStatusTracker::new(2);
+        assert_eq!(
+            tracker.on_update::<Slot>(0),
+            vec![MultiplexEvent::SourceReady { idx: 0 }]
+        );
+        assert!(tracker.on_update::<Slot>(0).is_empty());
+        assert_eq!(tracker.on_connecting::<Slot>(0, 2).len(), 2);
+        assert_eq!(
+            tracker.on_update::<Slot>(1),
+            vec![
+                MultiplexEvent::SourceReady { idx: 1 },
+                MultiplexEvent::Recovered
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reorder_window_slots() {
+        let source = futures::stream::iter(vec![
+            mock_message(mock_block_meta_update(101, 100)),
+            mock_message(mock_block_meta_update(103, 102)),
+            // late but never emitted
+            mock_message(mock_block_meta_update(102, 101)),
+            mock_message(mock_block_meta_update(105, 104)),
+            mock_message(mock_block_meta_update(104, 103)),
+            // duplicate
+            mock_message(mock_block_meta_update(104, 103)),
+        ]);
+
+        let items = only_items(create_multiplexed_event_stream(
+            vec![source],
+            BlockMetaSlotExtractor,
+            MultiplexConfig {
+                reorder_window: Some(ReorderWindow::Slots(2)),
+                ..Default::default()
+            },
+        ))
+        .collect::<Vec<_>>()
+        .await;
+
+        assert_eq!(items, vec![101, 102, 103, 104, 105]);
+    }
+
+    #[tokio::test]
+    async fn test_poisoned_tip() {
+        let source_a = futures::stream::iter(vec![
+            mock_message(mock_block_meta_update(101, 100)),
+            // bogus far-future slot
+            mock_message(mock_block_meta_update(999_999, 999_998)),
+            mock_message(mock_block_meta_update(102, 101)),
+        ]);
+        // second source which does not deliver anything
+        let source_b = futures::stream::iter(vec![]);
+
+        let events = create_multiplexed_event_stream(
+            vec![source_a, source_b],
+            BlockMetaSlotExtractor,
+            MultiplexConfig {
+                slot_sanity: Some(SlotSanityConfig::default()),
+                ..Default::default()
+            },
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+        assert_eq!(
+            events,
+            vec![
+                MultiplexEvent::SourceReady { idx: 0 },
+                MultiplexEvent::Item(101),
+                MultiplexEvent::SourceQuarantined {
+                    idx: 0,
+                    slot: 999_999
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_poisoned_tip_single_source() {
+        let source = futures::stream::iter(vec![
+            mock_message(mock_block_meta_update(101, 100)),
+            // bogus far-future slot is held back
+            mock_message(mock_block_meta_update(999_999, 999_998)),
+            mock_message(mock_block_meta_update(102, 101)),
+            mock_message(mock_block_meta_update(103, 102)),
+        ]);
+
+        let events = create_multiplexed_event_stream(
+            vec![source],
+            BlockMetaSlotExtractor,
+            MultiplexConfig {
+                slot_sanity: Some(SlotSanityConfig::default()),
+                ..Default::default()
+            },
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+        assert_eq!(
+            events,
+            vec![
+                MultiplexEvent::SourceReady { idx: 0 },
+                MultiplexEvent::Item(101),
+                MultiplexEvent::Item(102),
+                MultiplexEvent::Item(103),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reorder_window_duration() {
+        let mut buffer = ReorderBuffer::new(ReorderWindow::Duration(Duration::from_millis(100)));
+        let now = Instant::now();
+        assert!(buffer.insert(103_u64, "c", None, 0, now));
+        assert!(buffer.insert(102_u64, "b", None, 0, now + Duration::from_millis(50)));
+        assert!(!buffer.insert(102_u64, "b", None, 1, now + Duration::from_millis(60)));
+
+        assert!(buffer.pop_ready(now + Duration::from_millis(100)).is_none());
+        assert_eq!(
+            buffer
+                .pop_ready(now + Duration::from_millis(150))
+                .map(|(key, pending)| (key, pending.item)),
+            Some((102, "b"))
+        );
+        assert_eq!(
+            buffer
+                .pop_ready(now + Duration::from_millis(150))
+                .map(|(key, pending)| (key, pending.item)),
+            Some((103, "c"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_closure_extractor_with_multiple_items() {
+        let fast = futures::stream::iter(vec![
+            mock_message(mock_block_meta_update(101, 100)),
+            mock_message(mock_block_meta_update(102, 101)),
+        ]);
+        let slow = futures::stream::iter(vec![mock_message(mock_block_meta_update(101, 100))]);
+
+        // emit two items per update keyed by (slot, index)
+        let extractor =
+            extractor_fn(
+                |source_idx, update: SubscribeUpdate| match update.update_oneof {
+                    Some(UpdateOneof::BlockMeta(meta)) => vec![
+                        ((meta.slot, 0u32), (source_idx, meta.slot, 0)),
+                        ((meta.slot, 1u32), (source_idx, meta.slot, 1)),
+                    ],
+                    _ => vec![],
+                },
+            );
+
+        let items = create_multiplexed_stream(vec![fast, slow], extractor)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0].1, 101);
+        assert_eq!(items[1].1, 101);
+        // items of one slot come from the same source
+        assert_eq!(items[0].0, items[1].0);
+        assert_eq!(items[2], (0, 102, 0));
+        assert_eq!(items[3], (0, 102, 1));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use async_stream::stream;
+use futures::Stream;
+use log::{debug, info, warn};
+use solana_clock::Slot;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use yellowstone_grpc_proto::geyser::SubscribeRequest;
+
+use crate::grpcmultiplex_fastestwins::{
+    merge_tagged, parent_link, spawn_source_tasks, spawn_stream_forwarder,
+    FromYellowstoneExtractor, TaggedMessage,
+};
+use crate::{GrpcSourceConfig, Message};
+
+// number of slots below the highest slot for which parent links and emitted slots are kept
+const FORK_WINDOW_SLOTS: u64 = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ForkAwareEvent<T> {
+    Item(T),
+    // the next emitted item does not descend from the previous tip
+    ForkSwitch {
+        from_tip: Slot,
+        to_tip: Slot,
+        // None if the fork point is outside of the tracked window
+        common_ancestor: Option<Slot>,
+    },
+}
+
+/// parent links of recently seen slots
+pub struct SlotTree {
+    parents: BTreeMap<Slot, Slot>,
+    window_slots: u64,
+}
+
+impl SlotTree {
+    pub const fn new(window_slots: u64) -> Self {
+        SlotTree {
+            parents: BTreeMap::new(),
+            window_slots,
+        }
+    }
+
+    pub fn insert(&mut self, slot: Slot, parent: Slot) {
+        self.parents.insert(slot, parent);
+
+        let highest_slot = *self.parents.last_key_value().expect("not empty").0;
+        let lowest_slot = highest_slot.saturating_sub(self.window_slots);
+        self.parents = self.parents.split_off(&lowest_slot);
+    }
+
+    pub fn parent(&self, slot: Slot) -> Option<Slot> {
+        self.parents.get(&slot).copied()
+    }
+
+    // parent links must point to a lower slot; invalid links from a bad source end the walk
+    fn parent_below(&self, slot: Slot) -> Option<Slot> {
+        self.parent(slot).filter(|parent| *parent < slot)
+    }
+
+    /// Some(true) if slot is ancestor or equal; None if the chain is broken by unknown slots
+    pub fn descends_from(&self, slot: Slot, ancestor: Slot) -> Option<bool> {
+        let mut current = slot;
+        loop {
+            if current == ancestor {
+                return Some(true);
+            }
+            if current < ancestor {
+                return Some(false);
+            }
+            current = self.parent_below(current)?;
+        }
+    }
+
+    pub fn common_ancestor(&self, a: Slot, b: Slot) -> Option<Slot> {
+        let mut ancestors_of_a = HashSet::new();
+        let mut current = Some(a);
+        while let Some(slot) = current {
+            ancestors_of_a.insert(slot);
+            current = self.parent_below(slot);
+        }
+
+        let mut current = Some(b);
+        while let Some(slot) = current {
+            if ancestors_of_a.contains(&slot) {
+                return Some(slot);
+            }
+            current = self.parent_below(slot);
+        }
+        None
+    }
+}
+
+/// use streams created by ``create_geyser_reconnecting_stream``;
+/// fastest-wins multiplexer which is safe to use with commitment level "processed"
+///
+/// tracks the parent/child relationship of slots so that a fork at a lower slot is not dropped;
+/// emits ``ForkAwareEvent::ForkSwitch`` before the first item of a different fork
+///
+/// note: forks are tracked per slot, so this takes a ``FromYellowstoneExtractor`` (one item per slot)
+/// and not a general ``MultiplexExtractor``
+pub fn create_fork_aware_multiplexed_stream<E>(
+    grpc_source_streams: Vec<impl Stream<Item = Message>>,
+    extractor: E,
+) -> impl Stream<Item = ForkAwareEvent<E::Target>>
+where
+    E: FromYellowstoneExtractor,
+{
+    if grpc_source_streams.is_empty() {
+        panic!("Must have at least one grpc source");
+    }
+
+    info!(
+        "Starting fork-aware multiplexer with {} sources",
+        grpc_source_streams.len()
+    );
+
+    extract_fork_aware_from_geyser_updates(merge_tagged(grpc_source_streams), extractor)
+}
+
+/// spawns one autoconnect task per source; see ``create_fork_aware_multiplexed_stream``
+pub fn create_fork_aware_multiplexed_task<E>(
+    grpc_sources: Vec<GrpcSourceConfig>,
+    subscribe_filter: SubscribeRequest,
+    extractor: E,
+    exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, mpsc::Receiver<ForkAwareEvent<E::Target>>)
+where
+    E: FromYellowstoneExtractor + Send + 'static,
+    E::Target: Send + 'static,
+{
+    let source_streams = spawn_source_tasks(grpc_sources, subscribe_filter, &exit_notify);
+
+    spawn_stream_forwarder(
+        create_fork_aware_multiplexed_stream(source_streams, extractor),
+        exit_notify,
+    )
+}
+
+fn extract_fork_aware_from_geyser_updates<E>(
+    merged_stream: impl Stream<Item = TaggedMessage>,
+    extractor: E,
+) -> impl Stream<Item = ForkAwareEvent<E::Target>>
+where
+    E: FromYellowstoneExtractor,
+{
+    let mut slot_tree = SlotTree::new(FORK_WINDOW_SLOTS);
+    let mut emitted_slots: BTreeSet<Slot> = BTreeSet::new();
+    let mut tip: Option<Slot> = None;
+    stream! {
+        for await TaggedMessage {stream_idx, payload} in merged_stream {
+            match payload {
+                Message::GeyserSubscribeUpdate(update) => {
+                    if let Some((slot, parent)) = parent_link(&update) {
+                        slot_tree.insert(slot, parent);
+                    }
+
+                    let Some((proposed_slot, item)) = extractor.map_yellowstone_update(*update) else {
+                        continue;
+                    };
+
+                    let lowest_slot = emitted_slots.last().copied().unwrap_or_default().saturating_sub(FORK_WINDOW_SLOTS);
+                    if proposed_slot < lowest_slot || !emitted_slots.insert(proposed_slot) {
+                        // already emitted by faster source
+                        continue;
+                    }
+                    emitted_slots = emitted_slots.split_off(&lowest_slot);
+
+                    let Some(current_tip) = tip else {
+                        tip = Some(proposed_slot);
+                        yield ForkAwareEvent::Item(item);
+                        continue;
+                    };
+
+                    let switch_fork = if proposed_slot > current_tip {
+                        slot_tree.descends_from(proposed_slot, current_tip) == Some(false)
+                    } else {
+                        // ancestors of the tip which arrive late do not change the tip
+                        slot_tree.descends_from(current_tip, proposed_slot) == Some(false)
+                    };
+
+                    if switch_fork {
+                        let common_ancestor = slot_tree.common_ancestor(current_tip, proposed_slot);
+                        debug!("fork switch from {} to {} (common ancestor {:?})", current_tip, proposed_slot, common_ancestor);
+                        yield ForkAwareEvent::ForkSwitch {
+                            from_tip: current_tip,
+                            to_tip: proposed_slot,
+                            common_ancestor,
+                        };
+                        tip = Some(proposed_slot);
+                    } else if proposed_slot > current_tip {
+                        tip = Some(proposed_slot);
+                    }
+
+                    yield ForkAwareEvent::Item(item);
+                }
+                Message::Connecting(attempt) => {
+                    if attempt > 1 {
+                        warn!("Stream-{} performs reconnect attempt {}", stream_idx, attempt);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_block_meta_update, mock_message, BlockMetaSlotExtractor};
+    use futures::StreamExt;
+
+    #[test]
+    fn test_slot_tree() {
+        let mut tree = SlotTree::new(100);
+        tree.insert(101, 100);
+        tree.insert(102, 101);
+        tree.insert(103, 101);
+        assert_eq!(tree.descends_from(102, 101), Some(true));
+        assert_eq!(tree.descends_from(103, 102), Some(false));
+        assert_eq!(tree.descends_from(103, 99), None);
+        assert_eq!(tree.common_ancestor(102, 103), Some(101));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_parent_links() {
+        let mut tree = SlotTree::new(100);
+        tree.insert(101, 100);
+        tree.insert(102, 102);
+        tree.insert(103, 104);
+        assert_eq!(tree.descends_from(102, 101), None);
+        assert_eq!(tree.descends_from(103, 101), None);
+        assert_eq!(tree.common_ancestor(102, 103), None);
+        assert_eq!(tree.common_ancestor(101, 102), None);
+
+        // self-parent from a bad source must not stall the multiplexer
+        let source = futures::stream::iter(vec![
+            mock_message(mock_block_meta_update(101, 100)),
+            mock_message(mock_block_meta_update(102, 102)),
+            mock_message(mock_block_meta_update(103, 104)),
+        ]);
+        let events = create_fork_aware_multiplexed_stream(vec![source], BlockMetaSlotExtractor)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            events,
+            vec![
+                ForkAwareEvent::Item(101),
+                ForkAwareEvent::Item(102),
+                ForkAwareEvent::Item(103),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fork_at_lower_slot() {
+        let source = futures::stream::iter(vec![
+            mock_message(mock_block_meta_update(101, 100)),
+            mock_message(mock_block_meta_update(103, 101)),
+            // duplicate from slower source
+            mock_message(mock_block_meta_update(103, 101)),
+            // fork at lower slot
+            mock_message(mock_block_meta_update(102, 100)),
+            mock_message(mock_block_meta_update(104, 103)),
+        ]);
+
+        let events = create_fork_aware_multiplexed_stream(vec![source], BlockMetaSlotExtractor)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            events,
+            vec![
+                ForkAwareEvent::Item(101),
+                ForkAwareEvent::Item(103),
+                ForkAwareEvent::ForkSwitch {
+                    from_tip: 103,
+                    to_tip: 102,
+                    common_ancestor: Some(100),
+                },
+                ForkAwareEvent::Item(102),
+                ForkAwareEvent::ForkSwitch {
+                    from_tip: 102,
+                    to_tip: 104,
+                    common_ancestor: Some(100),
+                },
+                ForkAwareEvent::Item(104),
+            ]
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::BTreeMap;
+use std::pin::pin;
+use std::time::Duration;
+
+use async_stream::stream;
+use futures::{Stream, StreamExt};
+use log::{debug, info, warn};
+use solana_clock::Slot;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Instant, MissedTickBehavior};
+use yellowstone_grpc_proto::geyser::SubscribeRequest;
+
+use crate::grpcmultiplex_fastestwins::{
+    merge_tagged, spawn_source_tasks, spawn_stream_forwarder, FromYellowstoneExtractor,
+    TaggedMessage,
+};
+use crate::{GrpcSourceConfig, Message};
+
+// number of slots below the tip for which decisions are kept to detect late divergences
+const QUORUM_WINDOW_SLOTS: u64 = 128;
+
+#[derive(Clone, Debug)]
+pub struct QuorumConfig {
+    // number of sources which must deliver an identical payload (K of N)
+    pub quorum: usize,
+    // fall back to fastest-wins if no quorum is reached within this duration after the first arrival
+    pub timeout: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuorumEvent<T> {
+    Item(T),
+    // quorum reached for a slot below the tip of emitted items, i.e. out of slot order
+    LateItem { slot: Slot, tip: Slot, item: T },
+    // source delivered a payload which differs from the emitted one
+    Divergence { slot: Slot, source_idx: usize },
+    // no quorum reached in time; the payload delivered by most sources (first arrival on tie) gets emitted
+    QuorumTimeout { slot: Slot, agreeing_sources: usize },
+}
+
+struct Candidate<T> {
+    payload: T,
+    source_idxs: Vec<usize>,
+}
+
+struct PendingSlot<T> {
+    first_arrival: Instant,
+    // distinct payloads in order of first arrival
+    candidates: Vec<Candidate<T>>,
+}
+
+/// collects the payloads per slot until K sources agree
+pub struct QuorumTracker<T> {
+    config: QuorumConfig,
+    pending: BTreeMap<Slot, PendingSlot<T>>,
+    decided: BTreeMap<Slot, T>,
+    tip: Slot,
+}
+
+impl<T: PartialEq + Clone> QuorumTracker<T> {
+    pub const fn new(config: QuorumConfig) -> Self {
+        QuorumTracker {
+            config,
+            pending: BTreeMap::new(),
+            decided: BTreeMap::new(),
+            tip: 0,
+        }
+    }
+
+    pub fn on_update(
+        &mut self,
+        slot: Slot,
+        source_idx: usize,
+        payload: T,
+        now: Instant,
+    ) -> Vec<QuorumEvent<T>> {
+        if let Some(decided_payload) = self.decided.get(&slot) {
+            if *decided_payload != payload {
+                warn!(
+                    "Stream-{} diverges from emitted payload for slot {}",
+                    source_idx, slot
+                );
+                return vec![QuorumEvent::Divergence { slot, source_idx }];
+            }
+            return vec![];
+        }
+
+        if slot < self.tip.saturating_sub(QUORUM_WINDOW_SLOTS) {
+            return vec![];
+        }
+
+        let pending = self.pending.entry(slot).or_insert_with(|| PendingSlot {
+            first_arrival: now,
+            candidates: vec![],
+        });
+        let agreeing_sources = match pending
+            .candidates
+            .iter_mut()
+            .find(|candidate| candidate.payload == payload)
+        {
+            Some(candidate) => {
+                if !candidate.source_idxs.contains(&source_idx) {
+                    candidate.source_idxs.push(source_idx);
+                }
+                candidate.source_idxs.len()
+            }
+            None => {
+                pending.candidates.push(Candidate {
+                    payload,
+                    source_idxs: vec![source_idx],
+                });
+                1
+            }
+        };
+
+        if agreeing_sources >= self.config.quorum {
+            self.decide(slot)
+        } else {
+            vec![]
+        }
+    }
+
+    /// fall back to fastest-wins for slots which did not reach quorum in time
+    pub fn on_tick(&mut self, now: Instant) -> Vec<QuorumEvent<T>> {
+        let timed_out = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now.duration_since(pending.first_arrival) >= self.config.timeout)
+            .map(|(slot, _)| *slot)
+            .collect::<Vec<_>>();
+
+        let mut events = vec![];
+        for slot in timed_out {
+            let agreeing_sources = self.pending[&slot]
+                .candidates
+                .iter()
+                .map(|candidate| candidate.source_idxs.len())
+                .max()
+                .unwrap_or(0);
+            debug!(
+                "no quorum for slot {} after {:?} - fall back to fastest-wins",
+                slot, self.config.timeout
+            );
+            events.push(QuorumEvent::QuorumTimeout {
+                slot,
+                agreeing_sources,
+            });
+            events.extend(self.decide(slot));
+        }
+        events
+    }
+
+    fn decide(&mut self, slot: Slot) -> Vec<QuorumEvent<T>> {
+        let Some(pending) = self.pending.remove(&slot) else {
+            return vec![];
+        };
+
+        // max_by_key returns the last max element; iterate in reverse to prefer the first arrival
+        let Some(winner_idx) = pending
+            .candidates
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, candidate)| candidate.source_idxs.len())
+            .map(|(idx, _)| idx)
+        else {
+            return vec![];
+        };
+
+        let mut events = vec![];
+        let mut winner = None;
+        for (idx, candidate) in pending.candidates.into_iter().enumerate() {
+            if idx == winner_idx {
+                winner = Some(candidate.payload);
+            } else {
+                for source_idx in candidate.source_idxs {
+                    warn!(
+                        "Stream-{} diverges from quorum for slot {}",
+                        source_idx, slot
+                    );
+                    events.push(QuorumEvent::Divergence { slot, source_idx });
+                }
+            }
+        }
+        let winner = winner.expect("winner must exist");
+
+        self.decided.insert(slot, winner.clone());
+        if slot > self.tip {
+            self.tip = slot;
+            events.push(QuorumEvent::Item(winner));
+        } else {
+            debug!("quorum for slot {} reached behind tip {}", slot, self.tip);
+            events.push(QuorumEvent::LateItem {
+                slot,
+                tip: self.tip,
+                item: winner,
+            });
+        }
+
+        let lowest_slot = self.tip.saturating_sub(QUORUM_WINDOW_SLOTS);
+        self.decided = self.decided.split_off(&lowest_slot);
+        self.pending = self.pending.split_off(&lowest_slot);
+
+        events
+    }
+}
+
+/// use streams created by ``create_geyser_reconnecting_stream``;
+/// emits an item only once `quorum` sources delivered an identical payload for the slot;
+/// identity is defined by `PartialEq` of the extracted target (e.g. blockhash and parent slot for block meta)
+///
+/// note: votes are counted per slot, so this takes a ``FromYellowstoneExtractor`` (one item per slot)
+/// and not a general ``MultiplexExtractor``
+pub fn create_quorum_multiplexed_stream<E>(
+    grpc_source_streams: Vec<impl Stream<Item = Message>>,
+    extractor: E,
+    quorum_config: QuorumConfig,
+) -> impl Stream<Item = QuorumEvent<E::Target>>
+where
+    E: FromYellowstoneExtractor,
+    E::Target: PartialEq + Clone,
+{
+    if grpc_source_streams.is_empty() {
+        panic!("Must have at least one grpc source");
+    }
+    assert!(
+        quorum_config.quorum >= 1 && quorum_config.quorum <= grpc_source_streams.len(),
+        "quorum must be between 1 and number of sources"
+    );
+
+    info!(
+        "Starting quorum multiplexer with {} sources (quorum {})",
+        grpc_source_streams.len(),
+        quorum_config.quorum
+    );
+
+    extract_quorum_from_geyser_updates(merge_tagged(grpc_source_streams), extractor, quorum_config)
+}
+
+/// spawns one autoconnect task per source; see ``create_quorum_multiplexed_stream``
+pub fn create_quorum_multiplexed_task<E>(
+    grpc_sources: Vec<GrpcSourceConfig>,
+    subscribe_filter: SubscribeRequest,
+    extractor: E,
+    quorum_config: QuorumConfig,
+    exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, mpsc::Receiver<QuorumEvent<E::Target>>)
+where
+    E: FromYellowstoneExtractor + Send + 'static,
+    E::Target: PartialEq + Clone + Send + 'static,
+{
+    let source_streams = spawn_source_tasks(grpc_sources, subscribe_filter, &exit_notify);
+
+    spawn_stream_forwarder(
+        create_quorum_multiplexed_stream(source_streams, extractor, quorum_config),
+        exit_notify,
+    )
+}
+
+fn extract_quorum_from_geyser_updates<E>(
+    merged_stream: impl Stream<Item = TaggedMessage>,
+    extractor: E,
+    quorum_config: QuorumConfig,
+) -> impl Stream<Item = QuorumEvent<E::Target>>
+where
+    E: FromYellowstoneExtractor,
+    E::Target: PartialEq + Clone,
+{
+    let check_interval = (quorum_config.timeout / 4).max(Duration::from_millis(10));
+    let mut tracker = QuorumTracker::new(quorum_config);
+    stream! {
+        let mut merged_stream = pin!(merged_stream);
+        let mut timeout_check = interval(check_interval);
+        timeout_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            let events = tokio::select! {
+                next = merged_stream.next() => {
+                    match next {
+                        Some(TaggedMessage { stream_idx, payload: Message::GeyserSubscribeUpdate(update) }) => {
+                            match extractor.map_yellowstone_update(*update) {
+                                Some((slot, item)) => tracker.on_update(slot, stream_idx, item, Instant::now()),
+                                None => vec![],
+                            }
+                        }
+                        Some(TaggedMessage { stream_idx, payload: Message::Connecting(attempt) }) => {
+                            if attempt > 1 {
+                                warn!("Stream-{} performs reconnect attempt {}", stream_idx, attempt);
+                            }
+                            vec![]
+                        }
+                        None => break,
+                    }
+                }
+                _ = timeout_check.tick() => {
+                    tracker.on_tick(Instant::now())
+                }
+            };
+
+            for event in events {
+                yield event;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(quorum: usize) -> QuorumTracker<&'static str> {
+        QuorumTracker::new(QuorumConfig {
+            quorum,
+            timeout: Duration::from_millis(500),
+        })
+    }
+
+    #[test]
+    fn test_quorum_reached() {
+        let mut tracker = tracker(2);
+        let now = Instant::now();
+        assert!(tracker.on_update(100, 0, "hash-a", now).is_empty());
+        assert_eq!(
+            tracker.on_update(100, 1, "hash-a", now),
+            vec![QuorumEvent::Item("hash-a")]
+        );
+        // late source agrees
+        assert!(tracker.on_update(100, 2, "hash-a", now).is_empty());
+    }
+
+    #[test]
+    fn test_divergence() {
+        let mut tracker = tracker(2);
+        let now = Instant::now();
+        assert!(tracker.on_update(100, 0, "hash-bad", now).is_empty());
+        assert!(tracker.on_update(100, 1, "hash-a", now).is_empty());
+        assert_eq!(
+            tracker.on_update(100, 2, "hash-a", now),
+            vec![
+                QuorumEvent::Divergence {
+                    slot: 100,
+                    source_idx: 0
+                },
+                QuorumEvent::Item("hash-a"),
+            ]
+        );
+        assert_eq!(
+            tracker.on_update(100, 3, "hash-other", now),
+            vec![QuorumEvent::Divergence {
+                slot: 100,
+                source_idx: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn test_timeout_falls_back_to_fastest() {
+        let mut tracker = tracker(2);
+        let now = Instant::now();
+        assert!(tracker.on_update(100, 0, "hash-a", now).is_empty());
+        assert!(tracker.on_tick(now + Duration::from_millis(100)).is_empty());
+        assert_eq!(
+            tracker.on_tick(now + Duration::from_millis(500)),
+            vec![
+                QuorumEvent::QuorumTimeout {
+                    slot: 100,
+                    agreeing_sources: 1
+                },
+                QuorumEvent::Item("hash-a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quorum_behind_tip() {
+        let mut tracker = tracker(2);
+        let now = Instant::now();
+        assert!(tracker.on_update(100, 0, "hash-100", now).is_empty());
+        assert!(tracker.on_update(101, 0, "hash-101", now).is_empty());
+        assert_eq!(
+            tracker.on_update(101, 1, "hash-101", now),
+            vec![QuorumEvent::Item("hash-101")]
+        );
+        // slower quorum for the lower slot is not lost
+        assert_eq!(
+            tracker.on_update(100, 1, "hash-100", now),
+            vec![QuorumEvent::LateItem {
+                slot: 100,
+                tip: 101,
+                item: "hash-100"
+            }]
+        );
+    }
+}
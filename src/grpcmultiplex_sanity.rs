@@ -0,0 +1,302 @@
+use std::time::Duration;
+
+use log::warn;
+use solana_clock::Slot;
+use tokio::time::Instant;
+
+#[derive(Clone, Debug)]
+pub struct SlotSanityConfig {
+    // slots further ahead of the tip are implausible unless another source delivered a slot close to it
+    // or the time since the tip explains the jump
+    pub max_slot_jump: u64,
+    // expected duration of a slot; used for the wallclock check
+    pub slot_duration: Duration,
+    // updates from a source which delivered an implausible slot are ignored for this duration
+    pub quarantine: Duration,
+    // reset the tip if the other sources stay far behind it for this duration
+    pub recover_after: Duration,
+}
+
+impl Default for SlotSanityConfig {
+    fn default() -> Self {
+        SlotSanityConfig {
+            max_slot_jump: 150,
+            slot_duration: Duration::from_millis(400),
+            quarantine: Duration::from_secs(60),
+            recover_after: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum SlotVerdict {
+    Accept,
+    // source is in quarantine, or the slot of a single source is held back until a following slot confirms it
+    Ignore,
+    // slot is implausible; source was put into quarantine
+    Quarantine,
+    // tip was set by a source which got quarantined now; continue from the proposed slot
+    ResetTip { poisoned_source: usize, from: Slot },
+}
+
+struct SourceSanity {
+    last_slot: Option<Slot>,
+    // implausible slot which led to the quarantine (or was held back for a single source)
+    rejected_slot: Option<Slot>,
+    quarantined_until: Option<Instant>,
+}
+
+impl SourceSanity {
+    const fn new() -> Self {
+        SourceSanity {
+            last_slot: None,
+            rejected_slot: None,
+            quarantined_until: None,
+        }
+    }
+}
+
+/// guards the tip of the multiplexer against bogus far-future slots
+pub(crate) struct SlotSanityCheck {
+    config: SlotSanityConfig,
+    sources: Vec<SourceSanity>,
+    tip: Option<(Slot, usize, Instant)>,
+    // other sources are far behind the tip since
+    behind_since: Option<Instant>,
+}
+
+impl SlotSanityCheck {
+    pub(crate) fn new(config: SlotSanityConfig, num_sources: usize) -> Self {
+        SlotSanityCheck {
+            config,
+            sources: (0..num_sources).map(|_| SourceSanity::new()).collect(),
+            tip: None,
+            behind_since: None,
+        }
+    }
+
+    pub(crate) fn check(&mut self, source_idx: usize, slot: Slot, now: Instant) -> SlotVerdict {
+        if self.sources.len() <= source_idx {
+            self.sources.resize_with(source_idx + 1, SourceSanity::new);
+        }
+
+        if let Some(until) = self.sources[source_idx].quarantined_until {
+            if now < until {
+                return SlotVerdict::Ignore;
+            }
+            self.sources[source_idx].quarantined_until = None;
+        }
+
+        let Some((tip_slot, tip_source, _)) = self.tip else {
+            self.sources[source_idx].last_slot = Some(slot);
+            self.tip = Some((slot, source_idx, now));
+            return SlotVerdict::Accept;
+        };
+        let max_slot_jump = self.config.max_slot_jump;
+
+        let mut verdict = SlotVerdict::Accept;
+        if slot > tip_slot.saturating_add(max_slot_jump)
+            && !self.is_plausible(source_idx, slot, now)
+        {
+            // a single source cannot be cross-checked and quarantine would drop all updates;
+            // instead the jump is accepted once the source confirms it with a following slot
+            if self.sources.len() < 2 {
+                let confirmed = self.sources[source_idx]
+                    .rejected_slot
+                    .is_some_and(|held| held < slot && slot <= held.saturating_add(max_slot_jump));
+                if !confirmed {
+                    warn!(
+                        "Stream-{} delivered implausible slot {} (tip {}) - hold back until confirmed by a following slot",
+                        source_idx, slot, tip_slot
+                    );
+                    self.sources[source_idx].rejected_slot = Some(slot);
+                    return SlotVerdict::Ignore;
+                }
+                warn!(
+                    "Stream-{} confirmed slot jump to {} (tip {})",
+                    source_idx, slot, tip_slot
+                );
+                self.sources[source_idx].rejected_slot = None;
+            } else {
+                warn!(
+                    "Stream-{} delivered implausible slot {} (tip {}) - quarantine for {:?}",
+                    source_idx, slot, tip_slot, self.config.quarantine
+                );
+                self.quarantine(source_idx, slot, now);
+                return SlotVerdict::Quarantine;
+            }
+        }
+
+        if source_idx != tip_source {
+            if slot.saturating_add(max_slot_jump) < tip_slot {
+                let behind_since = *self.behind_since.get_or_insert(now);
+                if now.duration_since(behind_since) >= self.config.recover_after {
+                    warn!(
+                        "tip {} from Stream-{} is not confirmed by other sources - reset to slot {} and quarantine for {:?}",
+                        tip_slot, tip_source, slot, self.config.quarantine
+                    );
+                    self.quarantine(tip_source, tip_slot, now);
+                    self.tip = None;
+                    self.behind_since = None;
+                    verdict = SlotVerdict::ResetTip {
+                        poisoned_source: tip_source,
+                        from: tip_slot,
+                    };
+                }
+            } else {
+                // tip is confirmed by another source
+                self.behind_since = None;
+            }
+        }
+
+        let source = &mut self.sources[source_idx];
+        source.last_slot = source.last_slot.max(Some(slot));
+        match self.tip {
+            Some((tip_slot, _, _)) if slot <= tip_slot => {}
+            _ => self.tip = Some((slot, source_idx, now)),
+        }
+        verdict
+    }
+
+    fn is_plausible(&self, source_idx: usize, slot: Slot, now: Instant) -> bool {
+        let Some((tip_slot, _, tip_at)) = self.tip else {
+            return true;
+        };
+        let max_slot_jump = self.config.max_slot_jump;
+
+        // wallclock: slots advanced while no source delivered anything (e.g. all sources reconnected)
+        let elapsed_slots = (now.duration_since(tip_at).as_secs_f64()
+            / self.config.slot_duration.as_secs_f64()) as u64;
+        if slot
+            <= tip_slot
+                .saturating_add(elapsed_slots)
+                .saturating_add(max_slot_jump)
+        {
+            return true;
+        }
+
+        // another source delivered a slot close to it; includes slots which were rejected because nobody confirmed them
+        self.sources.iter().enumerate().any(|(idx, source)| {
+            idx != source_idx
+                && source
+                    .last_slot
+                    .max(source.rejected_slot)
+                    .is_some_and(|other_slot| other_slot.saturating_add(max_slot_jump) >= slot)
+        })
+    }
+
+    fn quarantine(&mut self, source_idx: usize, rejected_slot: Slot, now: Instant) {
+        let source = &mut self.sources[source_idx];
+        source.quarantined_until = Some(now + self.config.quarantine);
+        source.rejected_slot = Some(rejected_slot);
+        source.last_slot = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SlotSanityConfig {
+        SlotSanityConfig {
+            max_slot_jump: 100,
+            slot_duration: Duration::from_millis(400),
+            quarantine: Duration::from_secs(60),
+            recover_after: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn test_quarantine_far_future_slot() {
+        let mut check = SlotSanityCheck::new(config(), 2);
+        let now = Instant::now();
+        assert_eq!(check.check(0, 1000, now), SlotVerdict::Accept);
+        assert_eq!(check.check(1, 1001, now), SlotVerdict::Accept);
+        assert_eq!(check.check(1, 999_999, now), SlotVerdict::Quarantine);
+        assert_eq!(check.check(1, 1002, now), SlotVerdict::Ignore);
+        assert_eq!(check.check(0, 1002, now), SlotVerdict::Accept);
+        // quarantine is over
+        assert_eq!(
+            check.check(1, 1003, now + Duration::from_secs(60)),
+            SlotVerdict::Accept
+        );
+    }
+
+    #[test]
+    fn test_plausible_jumps() {
+        let mut check = SlotSanityCheck::new(config(), 2);
+        let now = Instant::now();
+        assert_eq!(check.check(0, 1000, now), SlotVerdict::Accept);
+        // explained by wallclock: 200 slots in 80 seconds
+        assert_eq!(
+            check.check(0, 1200, now + Duration::from_secs(80)),
+            SlotVerdict::Accept
+        );
+
+        let mut check = SlotSanityCheck::new(config(), 2);
+        assert_eq!(check.check(0, 1000, now), SlotVerdict::Accept);
+        assert_eq!(check.check(1, 1000, now), SlotVerdict::Accept);
+        assert_eq!(check.check(0, 5000, now), SlotVerdict::Quarantine);
+        // confirmed by the slot source 0 delivered before
+        assert_eq!(check.check(1, 5001, now), SlotVerdict::Accept);
+    }
+
+    #[test]
+    fn test_recover_poisoned_tip() {
+        let mut check = SlotSanityCheck::new(config(), 2);
+        let now = Instant::now();
+        // bogus slot arrives first
+        assert_eq!(check.check(1, 999_999, now), SlotVerdict::Accept);
+        assert_eq!(check.check(0, 1000, now), SlotVerdict::Accept);
+        assert_eq!(
+            check.check(0, 1001, now + Duration::from_secs(5)),
+            SlotVerdict::Accept
+        );
+        assert_eq!(
+            check.check(0, 1025, now + Duration::from_secs(10)),
+            SlotVerdict::ResetTip {
+                poisoned_source: 1,
+                from: 999_999
+            }
+        );
+        assert_eq!(
+            check.check(1, 1_000_000, now + Duration::from_secs(11)),
+            SlotVerdict::Ignore
+        );
+        assert_eq!(
+            check.check(0, 1026, now + Duration::from_secs(11)),
+            SlotVerdict::Accept
+        );
+    }
+
+    #[test]
+    fn test_single_source() {
+        let mut check = SlotSanityCheck::new(config(), 1);
+        let now = Instant::now();
+        assert_eq!(check.check(0, 1000, now), SlotVerdict::Accept);
+        // no other source to cross-check against; held back, no quarantine
+        assert_eq!(check.check(0, 5000, now), SlotVerdict::Ignore);
+        assert_eq!(check.check(0, 1001, now), SlotVerdict::Accept);
+        assert_eq!(check.check(0, 999_999, now), SlotVerdict::Ignore);
+        assert_eq!(check.check(0, 1002, now), SlotVerdict::Accept);
+        // jump confirmed by the following slot
+        assert_eq!(check.check(0, 1_000_000, now), SlotVerdict::Accept);
+        assert_eq!(check.check(0, 1_000_001, now), SlotVerdict::Accept);
+    }
+
+    #[test]
+    fn test_no_overflow_near_max_slot() {
+        let mut check = SlotSanityCheck::new(config(), 2);
+        let now = Instant::now();
+        assert_eq!(check.check(0, u64::MAX - 1, now), SlotVerdict::Accept);
+        assert_eq!(check.check(0, u64::MAX, now), SlotVerdict::Accept);
+        assert_eq!(check.check(1, 1000, now), SlotVerdict::Accept);
+        assert_eq!(
+            check.check(1, 1001, now + Duration::from_secs(10)),
+            SlotVerdict::ResetTip {
+                poisoned_source: 0,
+                from: u64::MAX
+            }
+        );
+    }
+}
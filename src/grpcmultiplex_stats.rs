@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use solana_clock::Slot;
+
+use crate::histogram_percentiles::{calculate_percentiles, Percentiles};
+
+// number of recent slots for which the first arrival is kept to measure the lag of slower sources
+const ARRIVAL_WINDOW_SLOTS: u64 = 128;
+// number of lag samples kept per source
+const MAX_LAG_SAMPLES: usize = 10_000;
+
+/// per-source win statistics of the fastest-wins multiplexer; cheap to clone
+#[derive(Clone, Default)]
+pub struct MultiplexStats {
+    inner: Arc<Mutex<MultiplexStatsInner>>,
+}
+
+#[derive(Default)]
+struct MultiplexStatsInner {
+    first_arrivals: BTreeMap<Slot, Instant>,
+    sources: Vec<SourceStats>,
+    emitted: u64,
+    tip: Slot,
+}
+
+#[derive(Default)]
+struct SourceStats {
+    wins: u64,
+    late_arrivals: u64,
+    // lag behind the winning source in milliseconds
+    lags_ms: VecDeque<f64>,
+    // highest slot delivered by the source
+    last_slot: Option<Slot>,
+}
+
+pub struct SourceStatsReport {
+    pub source_idx: usize,
+    pub wins: u64,
+    pub late_arrivals: u64,
+    // share of all emitted slots which were delivered first by this source
+    pub win_rate: f64,
+    pub lag_ms: Percentiles,
+}
+
+impl Display for SourceStatsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "source-{}: wins={}, late={}, win_rate={:.1}%, lag_ms: {}",
+            self.source_idx,
+            self.wins,
+            self.late_arrivals,
+            self.win_rate * 100.0,
+            self.lag_ms
+        )
+    }
+}
+
+impl MultiplexStats {
+    /// source delivered the slot first; the slot was emitted
+    pub(crate) fn record_win(&self, source_idx: usize, slot: Slot) {
+        let mut inner = self.inner.lock().expect("stats lock poisoned");
+        inner.emitted += 1;
+        inner.tip = inner.tip.max(slot);
+        let source = inner.source_mut(source_idx);
+        source.wins += 1;
+        source.last_slot = source.last_slot.max(Some(slot));
+        inner.first_arrivals.insert(slot, Instant::now());
+
+        let lowest_slot = slot.saturating_sub(ARRIVAL_WINDOW_SLOTS);
+        inner.first_arrivals = inner.first_arrivals.split_off(&lowest_slot);
+    }
+
+    /// source delivered a slot which was already emitted
+    pub(crate) fn record_late_arrival(&self, source_idx: usize, slot: Slot) {
+        let mut inner = self.inner.lock().expect("stats lock poisoned");
+        let source = inner.source_mut(source_idx);
+        source.last_slot = source.last_slot.max(Some(slot));
+
+        let Some(first_arrival) = inner.first_arrivals.get(&slot).copied() else {
+            // outside of window or never emitted
+            return;
+        };
+        let lag_ms = first_arrival.elapsed().as_secs_f64() * 1000.0;
+
+        let source = inner.source_mut(source_idx);
+        source.late_arrivals += 1;
+        if source.lags_ms.len() >= MAX_LAG_SAMPLES {
+            source.lags_ms.pop_front();
+        }
+        source.lags_ms.push_back(lag_ms);
+    }
+
+    pub fn win_rate(&self, source_idx: usize) -> f64 {
+        let inner = self.inner.lock().expect("stats lock poisoned");
+        inner.win_rate(source_idx)
+    }
+
+    /// lag behind the winning source in milliseconds; computed over the most recent samples
+    pub fn lag_percentiles(&self, source_idx: usize) -> Percentiles {
+        let inner = self.inner.lock().expect("stats lock poisoned");
+        inner.lag_percentiles(source_idx)
+    }
+
+    /// number of slots the source is behind the tip; None if the source did not deliver anything yet
+    pub fn slot_lag(&self, source_idx: usize) -> Option<u64> {
+        let inner = self.inner.lock().expect("stats lock poisoned");
+        let last_slot = inner.sources.get(source_idx)?.last_slot?;
+        Some(inner.tip.saturating_sub(last_slot))
+    }
+
+    /// tip was set by a bogus slot
+    pub(crate) fn reset_tip(&self, slot: Slot) {
+        let mut inner = self.inner.lock().expect("stats lock poisoned");
+        inner.tip = slot;
+    }
+
+    /// forget the progress of the source, e.g. after reconnect
+    pub(crate) fn reset_source(&self, source_idx: usize) {
+        let mut inner = self.inner.lock().expect("stats lock poisoned");
+        inner.source_mut(source_idx).last_slot = None;
+    }
+
+    pub fn report(&self) -> Vec<SourceStatsReport> {
+        let inner = self.inner.lock().expect("stats lock poisoned");
+        (0..inner.sources.len())
+            .map(|source_idx| SourceStatsReport {
+                source_idx,
+                wins: inner.sources[source_idx].wins,
+                late_arrivals: inner.sources[source_idx].late_arrivals,
+                win_rate: inner.win_rate(source_idx),
+                lag_ms: inner.lag_percentiles(source_idx),
+            })
+            .collect()
+    }
+}
+
+impl MultiplexStatsInner {
+    fn source_mut(&mut self, source_idx: usize) -> &mut SourceStats {
+        if self.sources.len() <= source_idx {
+            self.sources
+                .resize_with(source_idx + 1, SourceStats::default);
+        }
+        &mut self.sources[source_idx]
+    }
+
+    fn win_rate(&self, source_idx: usize) -> f64 {
+        if self.emitted == 0 {
+            return 0.0;
+        }
+        let wins = self.sources.get(source_idx).map(|s| s.wins).unwrap_or(0);
+        wins as f64 / self.emitted as f64
+    }
+
+    fn lag_percentiles(&self, source_idx: usize) -> Percentiles {
+        let mut lags_ms: Vec<f64> = self
+            .sources
+            .get(source_idx)
+            .map(|s| s.lags_ms.iter().copied().collect())
+            .unwrap_or_default();
+        lags_ms.sort_by(|a, b| a.total_cmp(b));
+        calculate_percentiles(&lags_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_win_rate() {
+        let stats = MultiplexStats::default();
+        stats.record_win(0, 100);
+        stats.record_late_arrival(1, 100);
+        stats.record_win(1, 101);
+        stats.record_late_arrival(0, 101);
+        stats.record_win(0, 102);
+        // slower source never delivered
+        stats.record_late_arrival(1, 99);
+
+        assert_eq!(stats.win_rate(0), 2.0 / 3.0);
+        assert_eq!(stats.win_rate(1), 1.0 / 3.0);
+        assert_eq!(stats.win_rate(2), 0.0);
+
+        let report = stats.report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[1].late_arrivals, 1);
+        assert_eq!(report[1].lag_ms.v.len(), 21);
+        assert!(report[1].lag_ms.v[0] >= 0.0);
+
+        assert_eq!(stats.slot_lag(0), Some(0));
+        assert_eq!(stats.slot_lag(1), Some(1));
+        stats.reset_source(1);
+        assert_eq!(stats.slot_lag(1), None);
+    }
+}
@@ -1,6 +1,9 @@
 use itertools::Itertools;
-use std::fmt::Display;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{Display, Write};
 use std::iter::zip;
+use std::time::{Duration, Instant};
 
 // #[derive(Clone, Copy, Debug, Default)]
 pub struct Point {
@@ -23,8 +26,64 @@ pub struct HistValue {
     pub value: f64,
 }
 
+/// percentiles to calculate
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PercentileSpec {
+    // every n percent from 0 to 100
+    Step(usize),
+    // percentiles in range 0.0..=1.0, e.g. 0.5, 0.9, 0.99
+    List(Vec<f64>),
+}
+
+impl Default for PercentileSpec {
+    fn default() -> Self {
+        PercentileSpec::Step(5)
+    }
+}
+
+impl PercentileSpec {
+    // (percentile as reported, quantile used for calculation)
+    fn quantiles(&self) -> Vec<(f32, f64)> {
+        match self {
+            PercentileSpec::Step(p_step) => {
+                assert!(*p_step > 0, "percentile step must be positive");
+                (0..=100)
+                    .step_by(*p_step)
+                    .map(|p| (p as f32 / 100.0, p as f64 / 100.0))
+                    .collect_vec()
+            }
+            PercentileSpec::List(quantiles) => quantiles
+                .iter()
+                .map(|&q| {
+                    assert!(
+                        (0.0..=1.0).contains(&q),
+                        "percentile must be in range 0.0..=1.0"
+                    );
+                    (q as f32, q)
+                })
+                .collect_vec(),
+        }
+    }
+}
+
+// index of the value at quantile q in a sorted array; tolerates float noise like 0.29 * 100 = 28.999999999999996
+fn quantile_index(len: usize, q: f64) -> usize {
+    let exact = len as f64 * q;
+    let rounded = exact.round();
+    let index = if (exact - rounded).abs() < 1e-9 {
+        rounded
+    } else {
+        exact.floor()
+    };
+    (index as usize).min(len - 1)
+}
+
 // `quantile` function is the same as the median if q=50, the same as the minimum if q=0 and the same as the maximum if q=100.
 pub fn calculate_percentiles(input: &[f64]) -> Percentiles {
+    calculate_percentiles_with_spec(input, &PercentileSpec::default())
+}
+
+pub fn calculate_percentiles_with_spec(input: &[f64], spec: &PercentileSpec) -> Percentiles {
     if input.is_empty() {
         // note: percentile for empty array is undefined
         return Percentiles {
@@ -36,20 +95,12 @@ pub fn calculate_percentiles(input: &[f64]) -> Percentiles {
     let is_monotonic = input.windows(2).all(|w| w[0] <= w[1]);
     assert!(is_monotonic, "array of values must be sorted");
 
-    let p_step = 5;
-    let i_percentiles = (0..=100).step_by(p_step).collect_vec();
-
-    let mut bucket_values = Vec::with_capacity(i_percentiles.len());
-    let mut percentiles = Vec::with_capacity(i_percentiles.len());
-    for p in i_percentiles {
-        let value = {
-            let index = input.len() * p / 100;
-            let cap_index = index.min(input.len() - 1);
-            input[cap_index]
-        };
-
-        bucket_values.push(value);
-        percentiles.push(p as f32 / 100.0);
+    let quantiles = spec.quantiles();
+    let mut bucket_values = Vec::with_capacity(quantiles.len());
+    let mut percentiles = Vec::with_capacity(quantiles.len());
+    for (p, q) in quantiles {
+        bucket_values.push(input[quantile_index(input.len(), q)]);
+        percentiles.push(p);
     }
 
     Percentiles {
@@ -59,7 +110,21 @@ pub fn calculate_percentiles(input: &[f64]) -> Percentiles {
 }
 
 pub fn calculate_cummulative(values: &[Point]) -> PercentilesCummulative {
-    if values.is_empty() {
+    calculate_weighted_percentiles(values, &PercentileSpec::default(), |point| {
+        (point.priority, point.value)
+    })
+}
+
+/// percentiles of values weighted by the second element returned from `value_and_weight`,
+/// e.g. priority fees weighted by consumed CU; items must be sorted by value
+///
+/// the bucket value is the smallest value such that the items up to this value have the given share of the total weight
+pub fn calculate_weighted_percentiles<T>(
+    items: &[T],
+    spec: &PercentileSpec,
+    value_and_weight: impl Fn(&T) -> (f64, f64),
+) -> PercentilesCummulative {
+    if items.is_empty() {
         // note: percentile for empty array is undefined
         return PercentilesCummulative {
             bucket_values: vec![],
@@ -67,37 +132,36 @@ pub fn calculate_cummulative(values: &[Point]) -> PercentilesCummulative {
         };
     }
 
-    let is_monotonic = values.windows(2).all(|w| w[0].priority <= w[1].priority);
+    let values = items.iter().map(value_and_weight).collect_vec();
+    let is_monotonic = values.windows(2).all(|w| w[0].0 <= w[1].0);
     assert!(is_monotonic, "array of values must be sorted");
 
-    let value_sum: f64 = values.iter().map(|x| x.value).sum();
-    let mut agg: f64 = values[0].value;
+    let weight_sum: f64 = values.iter().map(|(_, weight)| weight).sum();
+    let mut agg: f64 = values[0].1;
     let mut index = 0;
-    let p_step = 5;
 
-    let percentiles = (0..=100).step_by(p_step).map(|p| p as f64).collect_vec();
-
-    let dist = percentiles
-        .iter()
-        .map(|percentile| {
-            while agg < (value_sum * *percentile) / 100.0 {
+    let dist = spec
+        .quantiles()
+        .into_iter()
+        .map(|(percentile, q)| {
+            while agg < weight_sum * q && index + 1 < values.len() {
                 index += 1;
-                agg += values[index].value;
+                agg += values[index].1;
             }
-            let priority = values[index].priority;
             HistValue {
-                percentile: *percentile as f32,
-                value: priority,
+                percentile,
+                value: values[index].0,
             }
         })
         .collect_vec();
 
     PercentilesCummulative {
         bucket_values: dist.iter().map(|hv| hv.value).collect_vec(),
-        percentiles: dist.iter().map(|hv| hv.percentile / 100.0).collect_vec(),
+        percentiles: dist.iter().map(|hv| hv.percentile).collect_vec(),
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Percentiles {
     // value
     pub v: Vec<f64>,
@@ -114,26 +178,323 @@ impl Display for Percentiles {
     }
 }
 
-#[allow(dead_code)]
 impl Percentiles {
-    fn get_bucket_value(&self, percentile: f32) -> Option<f64> {
-        zip(&self.p, &self.v)
-            .find(|(&p, _v)| p == percentile)
-            .map(|(_p, &v)| v)
+    /// value for percentile in range 0.0..1.0; must be one of the calculated percentiles
+    pub fn get_bucket_value(&self, percentile: f32) -> Option<f64> {
+        lookup_bucket_value(&self.p, &self.v, percentile)
+    }
+
+    /// (percentile, value) pairs in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = (f32, f64)> + '_ {
+        zip(self.p.iter().copied(), self.v.iter().copied())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("percentiles are serializable")
+    }
+
+    /// one line per percentile with header `percentile,value`
+    pub fn to_csv(&self) -> String {
+        format_csv(&self.p, &self.v)
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PercentilesCummulative {
     pub bucket_values: Vec<f64>,
     pub percentiles: Vec<f32>,
 }
 
-#[allow(dead_code)]
+impl Display for PercentilesCummulative {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for i in 0..self.bucket_values.len() {
+            write!(
+                f,
+                "p{}=>{} ",
+                self.percentiles[i] * 100.0,
+                self.bucket_values[i]
+            )?;
+        }
+        Ok(())
+    }
+}
+
 impl PercentilesCummulative {
-    fn get_bucket_value(&self, percentile: f32) -> Option<f64> {
-        zip(&self.percentiles, &self.bucket_values)
-            .find(|(&p, _cu)| p == percentile)
-            .map(|(_p, &cu)| cu)
+    /// value for percentile in range 0.0..1.0; must be one of the calculated percentiles
+    pub fn get_bucket_value(&self, percentile: f32) -> Option<f64> {
+        lookup_bucket_value(&self.percentiles, &self.bucket_values, percentile)
+    }
+
+    /// (percentile, value) pairs in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = (f32, f64)> + '_ {
+        zip(
+            self.percentiles.iter().copied(),
+            self.bucket_values.iter().copied(),
+        )
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("percentiles are serializable")
+    }
+
+    /// one line per percentile with header `percentile,value`
+    pub fn to_csv(&self) -> String {
+        format_csv(&self.percentiles, &self.bucket_values)
+    }
+}
+
+fn lookup_bucket_value(percentiles: &[f32], values: &[f64], percentile: f32) -> Option<f64> {
+    // percentiles from a spec list went through a f64 to f32 conversion
+    zip(percentiles, values)
+        .find(|(&p, _v)| (p - percentile).abs() < 1e-6)
+        .map(|(_p, &v)| v)
+}
+
+fn format_csv(percentiles: &[f32], values: &[f64]) -> String {
+    let mut csv = String::from("percentile,value\n");
+    for (p, v) in zip(percentiles, values) {
+        writeln!(csv, "{},{}", p, v).expect("write to string");
+    }
+    csv
+}
+
+/// mergeable streaming quantile sketch with bounded relative error (log-scaled buckets like DDSketch/HDR histogram)
+///
+/// memory does not grow with the number of recorded values but only with the dynamic range of the values;
+/// sketches with the same relative accuracy can be merged (e.g. per-source sketches into a total)
+#[derive(Clone, Debug)]
+pub struct QuantileSketch {
+    relative_accuracy: f64,
+    gamma_ln: f64,
+    // bucket index => count; separate stores for positive and negative values
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    // values too close to zero to be indexed
+    zero_count: u64,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+// values with smaller magnitude are counted as zero
+const MIN_INDEXABLE_VALUE: f64 = 1e-9;
+
+impl Default for QuantileSketch {
+    fn default() -> Self {
+        QuantileSketch::new(0.01)
+    }
+}
+
+impl QuantileSketch {
+    /// relative_accuracy: max relative error of the quantile values, e.g. 0.01 for 1%
+    pub fn new(relative_accuracy: f64) -> Self {
+        assert!(
+            relative_accuracy > 0.0 && relative_accuracy < 1.0,
+            "relative accuracy must be in range 0.0..1.0"
+        );
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        QuantileSketch {
+            relative_accuracy,
+            gamma_ln: gamma.ln(),
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+            zero_count: 0,
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub const fn relative_accuracy(&self) -> f64 {
+        self.relative_accuracy
+    }
+
+    pub const fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (!self.is_empty()).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (!self.is_empty()).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (!self.is_empty()).then(|| self.sum / self.count as f64)
+    }
+
+    pub fn record(&mut self, value: f64) {
+        self.record_n(value, 1);
+    }
+
+    pub fn record_n(&mut self, value: f64, n: u64) {
+        if value.is_nan() || n == 0 {
+            return;
+        }
+        if value >= MIN_INDEXABLE_VALUE {
+            *self.positive.entry(self.bucket_index(value)).or_default() += n;
+        } else if value <= -MIN_INDEXABLE_VALUE {
+            *self.negative.entry(self.bucket_index(-value)).or_default() += n;
+        } else {
+            self.zero_count += n;
+        }
+        self.count += n;
+        self.sum += value * n as f64;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// panics if the sketches were created with different relative accuracy
+    pub fn merge(&mut self, other: &QuantileSketch) {
+        assert_eq!(
+            self.relative_accuracy, other.relative_accuracy,
+            "cannot merge sketches with different relative accuracy"
+        );
+        for (&index, &n) in &other.positive {
+            *self.positive.entry(index).or_default() += n;
+        }
+        for (&index, &n) in &other.negative {
+            *self.negative.entry(index).or_default() += n;
+        }
+        self.zero_count += other.zero_count;
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn clear(&mut self) {
+        *self = QuantileSketch::new(self.relative_accuracy);
+    }
+
+    /// quantile in range 0.0..=1.0; same rank semantics as ``calculate_percentiles``
+    pub fn quantile(&self, quantile: f64) -> Option<f64> {
+        if self.is_empty() || !(0.0..=1.0).contains(&quantile) {
+            return None;
+        }
+        // extremes are tracked exactly
+        if quantile == 0.0 {
+            return Some(self.min);
+        }
+        if quantile == 1.0 {
+            return Some(self.max);
+        }
+        let rank = quantile_index(self.count as usize, quantile) as u64;
+
+        let mut seen = 0;
+        // negative values in ascending order: highest magnitude first
+        for (&index, &n) in self.negative.iter().rev() {
+            seen += n;
+            if seen > rank {
+                return Some(-self.bucket_value(index).clamp(-self.max, -self.min));
+            }
+        }
+        seen += self.zero_count;
+        if seen > rank {
+            return Some(0.0);
+        }
+        for (&index, &n) in &self.positive {
+            seen += n;
+            if seen > rank {
+                return Some(self.bucket_value(index).clamp(self.min, self.max));
+            }
+        }
+        Some(self.max)
+    }
+
+    /// percentiles in steps of 5 like ``calculate_percentiles``
+    pub fn percentiles(&self) -> Percentiles {
+        self.percentiles_with_spec(&PercentileSpec::default())
+    }
+
+    pub fn percentiles_with_spec(&self, spec: &PercentileSpec) -> Percentiles {
+        let (p, v) = spec
+            .quantiles()
+            .into_iter()
+            .filter_map(|(p, q)| Some((p, self.quantile(q)?)))
+            .unzip();
+        Percentiles { v, p }
+    }
+
+    fn bucket_index(&self, magnitude: f64) -> i32 {
+        (magnitude.ln() / self.gamma_ln).ceil() as i32
+    }
+
+    // value with minimal relative error to all values of bucket (gamma^(i-1), gamma^i]
+    fn bucket_value(&self, index: i32) -> f64 {
+        let gamma = self.gamma_ln.exp();
+        2.0 * (self.gamma_ln * index as f64).exp() / (gamma + 1.0)
+    }
+}
+
+/// quantile sketch over a sliding time window; the window is split into slices which get rotated out as a whole
+pub struct WindowedQuantileSketch {
+    relative_accuracy: f64,
+    slice_duration: Duration,
+    max_slices: usize,
+    // (start of slice, sketch) - oldest first
+    slices: VecDeque<(Instant, QuantileSketch)>,
+}
+
+impl WindowedQuantileSketch {
+    /// the effective window is between window - window/num_slices and window
+    pub fn new(relative_accuracy: f64, window: Duration, num_slices: usize) -> Self {
+        assert!(num_slices > 0, "must have at least one slice");
+        WindowedQuantileSketch {
+            relative_accuracy,
+            slice_duration: window / num_slices as u32,
+            max_slices: num_slices,
+            slices: VecDeque::with_capacity(num_slices),
+        }
+    }
+
+    pub fn record(&mut self, value: f64, now: Instant) {
+        self.rotate(now);
+        let needs_new_slice = match self.slices.back() {
+            Some((started_at, _)) => now.duration_since(*started_at) >= self.slice_duration,
+            None => true,
+        };
+        if needs_new_slice {
+            if self.slices.len() == self.max_slices {
+                self.slices.pop_front();
+            }
+            self.slices
+                .push_back((now, QuantileSketch::new(self.relative_accuracy)));
+        }
+        self.slices
+            .back_mut()
+            .expect("slice was added")
+            .1
+            .record(value);
+    }
+
+    /// drop slices which are completely outside the window
+    pub fn rotate(&mut self, now: Instant) {
+        let window = self.slice_duration * self.max_slices as u32;
+        while let Some((started_at, _)) = self.slices.front() {
+            if now.duration_since(*started_at) < window {
+                break;
+            }
+            self.slices.pop_front();
+        }
+    }
+
+    /// merged sketch of all values within the window
+    pub fn snapshot(&mut self, now: Instant) -> QuantileSketch {
+        self.rotate(now);
+        let mut merged = QuantileSketch::new(self.relative_accuracy);
+        for (_, sketch) in &self.slices {
+            merged.merge(sketch);
+        }
+        merged
     }
 }
 
@@ -240,4 +601,141 @@ mod tests {
         assert_eq!(percentiles.v[19], 950.0);
         assert_eq!(percentiles.p[19], 0.95);
     }
+
+    #[test]
+    fn test_percentile_spec() {
+        let values = (0..1000).map(|i| i as f64).collect_vec();
+        let percentiles =
+            calculate_percentiles_with_spec(&values, &PercentileSpec::List(vec![0.5, 0.9, 0.99]));
+        assert_eq!(percentiles.v, vec![500.0, 900.0, 990.0]);
+        assert_eq!(percentiles.get_bucket_value(0.99), Some(990.0));
+        assert_eq!(percentiles.get_bucket_value(0.95), None);
+
+        let percentiles = calculate_percentiles_with_spec(&values, &PercentileSpec::Step(25));
+        assert_eq!(percentiles.p, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(percentiles.v, vec![0.0, 250.0, 500.0, 750.0, 999.0]);
+        // float noise: 0.29 * 100 = 28.999999999999996
+        let values = (0..100).map(|i| i as f64).collect_vec();
+        let percentiles =
+            calculate_percentiles_with_spec(&values, &PercentileSpec::List(vec![0.29]));
+        assert_eq!(percentiles.v, vec![29.0]);
+    }
+
+    #[test]
+    fn test_weighted_percentiles() {
+        // (priority fee, cu consumed)
+        let fees = vec![(10u64, 1_000u32), (20, 200_000), (5_000, 1_400_000)];
+        let percentiles = calculate_weighted_percentiles(
+            &fees,
+            &PercentileSpec::List(vec![0.0, 0.1, 0.5, 1.0]),
+            |(fee, cu)| (*fee as f64, *cu as f64),
+        );
+        assert_eq!(
+            percentiles.bucket_values,
+            vec![10.0, 20.0, 5_000.0, 5_000.0]
+        );
+        assert_eq!(percentiles.get_bucket_value(0.1), Some(20.0));
+        assert_eq!(
+            percentiles.iter().collect_vec(),
+            vec![(0.0, 10.0), (0.1, 20.0), (0.5, 5_000.0), (1.0, 5_000.0)]
+        );
+    }
+
+    #[test]
+    fn test_percentiles_output() {
+        let percentiles = calculate_percentiles_with_spec(
+            &[1.0, 2.0, 3.0, 4.0],
+            &PercentileSpec::List(vec![0.5, 1.0]),
+        );
+        assert_eq!(percentiles.to_csv(), "percentile,value\n0.5,3\n1,4\n");
+        assert_eq!(percentiles.to_json(), r#"{"v":[3.0,4.0],"p":[0.5,1.0]}"#);
+        let parsed: Percentiles = serde_json::from_str(&percentiles.to_json()).unwrap();
+        assert_eq!(parsed.v, percentiles.v);
+    }
+
+    #[test]
+    fn test_sketch_accuracy() {
+        // skewed distribution like latencies
+        let mut values = (1..=10_000)
+            .map(|i| (i as f64 / 100.0).powi(3) + 0.5)
+            .collect_vec();
+        let mut sketch = QuantileSketch::new(0.01);
+        for value in &values {
+            sketch.record(*value);
+        }
+        values.sort_by(f64::total_cmp);
+
+        let exact = calculate_percentiles(&values);
+        let approx = sketch.percentiles();
+        assert_eq!(exact.p, approx.p);
+        for (exact, approx) in zip(&exact.v, &approx.v) {
+            assert!(
+                (approx - exact).abs() <= exact.abs() * 0.01,
+                "exact={} approx={}",
+                exact,
+                approx
+            );
+        }
+        assert_eq!(sketch.quantile(0.0), Some(values[0]));
+        assert_eq!(sketch.quantile(1.0), Some(values[9999]));
+        assert_eq!(sketch.count(), 10_000);
+    }
+
+    #[test]
+    fn test_sketch_negative_and_zero() {
+        let mut values = vec![-50.0, -3.0, 0.0, 0.0, 2.0, 7.0, 100.0];
+        let mut sketch = QuantileSketch::new(0.02);
+        for value in &values {
+            sketch.record(*value);
+        }
+        values.sort_by(f64::total_cmp);
+        let exact = calculate_percentiles(&values);
+        let approx = sketch.percentiles();
+        for (exact, approx) in zip(&exact.v, &approx.v) {
+            assert!((approx - exact).abs() <= exact.abs() * 0.02);
+        }
+        assert!(QuantileSketch::default().quantile(0.5).is_none());
+    }
+
+    #[test]
+    fn test_sketch_merge() {
+        let mut left = QuantileSketch::new(0.01);
+        let mut right = QuantileSketch::new(0.01);
+        let mut total = QuantileSketch::new(0.01);
+        for i in 0..1000 {
+            let value = i as f64;
+            if i % 3 == 0 {
+                left.record(value);
+            } else {
+                right.record(value);
+            }
+            total.record(value);
+        }
+        left.merge(&right);
+        assert_eq!(left.count(), total.count());
+        for q in [0.0, 0.25, 0.5, 0.9, 0.99, 1.0] {
+            assert_eq!(left.quantile(q), total.quantile(q));
+        }
+    }
+
+    #[test]
+    fn test_windowed_sketch() {
+        let start = Instant::now();
+        let mut windowed = WindowedQuantileSketch::new(0.01, Duration::from_secs(60), 6);
+        for i in 0..60 {
+            windowed.record(1000.0, start + Duration::from_secs(i));
+        }
+        for i in 60..90 {
+            windowed.record(10.0, start + Duration::from_secs(i));
+        }
+
+        // slices starting before second 40 are rotated out
+        let snapshot = windowed.snapshot(start + Duration::from_secs(99));
+        assert_eq!(snapshot.count(), 50);
+        assert_eq!(snapshot.max(), Some(1000.0));
+        assert_eq!(snapshot.min(), Some(10.0));
+
+        let snapshot = windowed.snapshot(start + Duration::from_secs(200));
+        assert!(snapshot.is_empty());
+    }
 }
@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::info;
+use solana_clock::Slot;
+use tokio::select;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::interval;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::SubscribeUpdate;
+
+use crate::grpc_message_router::UpdateKind;
+use crate::histogram_percentiles::{PercentileSpec, Percentiles, WindowedQuantileSketch};
+
+#[derive(Clone, Debug)]
+pub struct LatencyTrackerConfig {
+    // latencies are reported over this rolling window
+    pub window: Duration,
+    // the window is rotated in this many steps
+    pub window_slices: usize,
+    // relative accuracy of the reported percentiles
+    pub relative_accuracy: f64,
+    // used to estimate the time of slots without a block time
+    pub slot_duration: Duration,
+    pub report_percentiles: PercentileSpec,
+}
+
+impl Default for LatencyTrackerConfig {
+    fn default() -> Self {
+        LatencyTrackerConfig {
+            window: Duration::from_secs(60),
+            window_slices: 6,
+            relative_accuracy: 0.01,
+            slot_duration: Duration::from_millis(400),
+            report_percentiles: PercentileSpec::List(vec![0.5, 0.9, 0.99, 1.0]),
+        }
+    }
+}
+
+/// end-to-end latency of geyser updates per source and update kind; cheap to clone
+///
+/// transport latency: from `created_at` (set by the geyser server) to receive time
+///
+/// chain latency: from the block time to receive time; for updates without a block time the slot time is estimated
+/// from the most recent block time and the slot duration
+///
+/// note: latencies depend on the clock sync between server, validator and client and may become negative
+#[derive(Clone)]
+pub struct LatencyTracker {
+    inner: Arc<Mutex<LatencyTrackerInner>>,
+}
+
+struct LatencyTrackerInner {
+    config: LatencyTrackerConfig,
+    histograms: BTreeMap<(String, UpdateKind), LatencyHistograms>,
+    // most recent (slot, block time in ms)
+    slot_time_anchor: Option<(Slot, i64)>,
+}
+
+struct LatencyHistograms {
+    transport_ms: WindowedQuantileSketch,
+    chain_ms: WindowedQuantileSketch,
+}
+
+pub struct LatencyReport {
+    pub source: String,
+    pub kind: UpdateKind,
+    // number of transport latency samples within the window
+    pub count: u64,
+    pub transport_ms: Percentiles,
+    pub chain_ms: Percentiles,
+}
+
+impl Display for LatencyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:?}: count={}, transport_ms: {}, chain_ms: {}",
+            self.source, self.kind, self.count, self.transport_ms, self.chain_ms
+        )
+    }
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        LatencyTracker::new(LatencyTrackerConfig::default())
+    }
+}
+
+impl LatencyTracker {
+    pub fn new(config: LatencyTrackerConfig) -> Self {
+        LatencyTracker {
+            inner: Arc::new(Mutex::new(LatencyTrackerInner {
+                config,
+                histograms: BTreeMap::new(),
+                slot_time_anchor: None,
+            })),
+        }
+    }
+
+    /// record latencies of an update received from the source now
+    pub fn record(&self, source: &str, update: &SubscribeUpdate) {
+        self.record_at(source, update, SystemTime::now(), Instant::now());
+    }
+
+    fn record_at(
+        &self,
+        source: &str,
+        update: &SubscribeUpdate,
+        received_at: SystemTime,
+        now: Instant,
+    ) {
+        let Some(update_oneof) = &update.update_oneof else {
+            return;
+        };
+        let kind = UpdateKind::of(update_oneof);
+        let received_at_ms = unix_millis(received_at);
+
+        let mut inner = self.inner.lock().expect("latency tracker lock poisoned");
+
+        let slot_and_block_time = match update_oneof {
+            UpdateOneof::Block(block) => {
+                Some((block.slot, block.block_time.as_ref().map(|t| t.timestamp)))
+            }
+            UpdateOneof::BlockMeta(block_meta) => Some((
+                block_meta.slot,
+                block_meta.block_time.as_ref().map(|t| t.timestamp),
+            )),
+            _ => update_slot(update_oneof).map(|slot| (slot, None)),
+        };
+        let chain_time_ms = match slot_and_block_time {
+            Some((slot, Some(block_time))) => {
+                let block_time_ms = block_time * 1000;
+                if inner
+                    .slot_time_anchor
+                    .is_none_or(|(anchor_slot, _)| slot >= anchor_slot)
+                {
+                    inner.slot_time_anchor = Some((slot, block_time_ms));
+                }
+                Some(block_time_ms)
+            }
+            Some((slot, None)) => inner.estimate_slot_time_ms(slot),
+            None => None,
+        };
+
+        let config = inner.config.clone();
+        let histograms = inner
+            .histograms
+            .entry((source.to_string(), kind))
+            .or_insert_with(|| LatencyHistograms {
+                transport_ms: WindowedQuantileSketch::new(
+                    config.relative_accuracy,
+                    config.window,
+                    config.window_slices,
+                ),
+                chain_ms: WindowedQuantileSketch::new(
+                    config.relative_accuracy,
+                    config.window,
+                    config.window_slices,
+                ),
+            });
+
+        if let Some(created_at) = &update.created_at {
+            let created_at_ms = created_at.seconds * 1000 + created_at.nanos as i64 / 1_000_000;
+            histograms
+                .transport_ms
+                .record((received_at_ms - created_at_ms) as f64, now);
+        }
+        if let Some(chain_time_ms) = chain_time_ms {
+            histograms
+                .chain_ms
+                .record((received_at_ms - chain_time_ms) as f64, now);
+        }
+    }
+
+    /// latency percentiles over the rolling window per source and update kind
+    pub fn report(&self) -> Vec<LatencyReport> {
+        self.report_at(Instant::now())
+    }
+
+    fn report_at(&self, now: Instant) -> Vec<LatencyReport> {
+        let mut inner = self.inner.lock().expect("latency tracker lock poisoned");
+        let spec = inner.config.report_percentiles.clone();
+        inner
+            .histograms
+            .iter_mut()
+            .filter_map(|((source, kind), histograms)| {
+                let transport_ms = histograms.transport_ms.snapshot(now);
+                let chain_ms = histograms.chain_ms.snapshot(now);
+                if transport_ms.is_empty() && chain_ms.is_empty() {
+                    return None;
+                }
+                Some(LatencyReport {
+                    source: source.clone(),
+                    kind: *kind,
+                    count: transport_ms.count(),
+                    transport_ms: transport_ms.percentiles_with_spec(&spec),
+                    chain_ms: chain_ms.percentiles_with_spec(&spec),
+                })
+            })
+            .collect()
+    }
+}
+
+impl LatencyTrackerInner {
+    fn estimate_slot_time_ms(&self, slot: Slot) -> Option<i64> {
+        let (anchor_slot, anchor_time_ms) = self.slot_time_anchor?;
+        let slot_duration_ms = self.config.slot_duration.as_millis() as i64;
+        Some(anchor_time_ms + (slot as i64 - anchor_slot as i64) * slot_duration_ms)
+    }
+}
+
+/// log a latency report periodically until exit is signaled
+pub fn spawn_latency_reporter(
+    latency_tracker: LatencyTracker,
+    report_interval: Duration,
+    mut exit_notify: broadcast::Receiver<()>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut report_interval = interval(report_interval);
+        // first tick completes immediately
+        report_interval.tick().await;
+        loop {
+            select! {
+                _ = exit_notify.recv() => {
+                    break;
+                }
+                _ = report_interval.tick() => {
+                    for report in latency_tracker.report() {
+                        info!("latency {}", report);
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch.as_millis() as i64,
+        Err(before_epoch) => -(before_epoch.duration().as_millis() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_block_meta_update, mock_slot_update};
+    use yellowstone_grpc_proto::prelude::UnixTimestamp;
+
+    fn with_created_at(mut update: SubscribeUpdate, created_at_ms: i64) -> SubscribeUpdate {
+        let created_at = update.created_at.insert(Default::default());
+        created_at.seconds = created_at_ms / 1000;
+        created_at.nanos = (created_at_ms % 1000) as i32 * 1_000_000;
+        update
+    }
+
+    fn block_meta_update(slot: Slot, block_time: i64) -> SubscribeUpdate {
+        let mut update = mock_block_meta_update(slot, slot - 1);
+        if let Some(UpdateOneof::BlockMeta(block_meta)) = &mut update.update_oneof {
+            block_meta.block_time = Some(UnixTimestamp {
+                timestamp: block_time,
+            });
+        }
+        update
+    }
+
+    #[test]
+    fn test_transport_and_chain_latency() {
+        let tracker = LatencyTracker::default();
+        let now = Instant::now();
+        let block_time = 1_700_000_000;
+        let received_at = UNIX_EPOCH + Duration::from_millis(block_time as u64 * 1000 + 800);
+
+        tracker.record_at(
+            "source-a",
+            &with_created_at(block_meta_update(1000, block_time), block_time * 1000 + 750),
+            received_at,
+            now,
+        );
+        // slot 1002 is expected 800ms after slot 1000
+        tracker.record_at(
+            "source-a",
+            &with_created_at(mock_slot_update(1002), block_time * 1000 + 780),
+            received_at + Duration::from_millis(900),
+            now,
+        );
+        tracker.record_at("source-b", &mock_slot_update(1002), received_at, now);
+
+        let reports = tracker.report_at(now);
+        assert_eq!(reports.len(), 3);
+
+        // ordered by source and update kind
+        let slot = &reports[0];
+        assert_eq!(slot.source, "source-a");
+        assert_eq!(slot.kind, UpdateKind::Slot);
+        assert_eq!(slot.transport_ms.get_bucket_value(0.5), Some(920.0));
+        assert_eq!(slot.chain_ms.get_bucket_value(0.5), Some(900.0));
+
+        let block_meta = &reports[1];
+        assert_eq!(block_meta.source, "source-a");
+        assert_eq!(block_meta.kind, UpdateKind::BlockMeta);
+        assert_eq!(block_meta.count, 1);
+        assert_eq!(block_meta.transport_ms.get_bucket_value(0.5), Some(50.0));
+        assert_eq!(block_meta.chain_ms.get_bucket_value(0.5), Some(800.0));
+
+        // no created_at
+        let slot_b = &reports[2];
+        assert_eq!(slot_b.source, "source-b");
+        assert_eq!(slot_b.count, 0);
+        assert!(slot_b.transport_ms.v.is_empty());
+        assert_eq!(slot_b.chain_ms.get_bucket_value(0.5), Some(0.0));
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let tracker = LatencyTracker::default();
+        let now = Instant::now();
+        let received_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        tracker.record_at(
+            "source-a",
+            &with_created_at(mock_slot_update(1), 1_700_000_000_000 - 20),
+            received_at,
+            now,
+        );
+        assert_eq!(tracker.report_at(now + Duration::from_secs(30)).len(), 1);
+        assert!(tracker.report_at(now + Duration::from_secs(61)).is_empty());
+    }
+}
@@ -12,17 +12,37 @@ use yellowstone_grpc_proto::geyser::{
 };
 use yellowstone_grpc_proto::tonic::transport::ClientTlsConfig;
 
+use crate::fault_injection::FaultInjector;
 use crate::obfuscate::url_obfuscate_api_token;
 pub use yellowstone_grpc_client::{
     GeyserGrpcClient, GeyserGrpcClientError, GeyserGrpcClientResult,
 };
 
+pub mod account_cache;
 pub mod channel_plugger;
+pub mod connection_stats;
+pub mod export;
+pub mod fault_injection;
+pub mod grpc_message_router;
 pub mod grpc_subscription_autoreconnect_streams;
 pub mod grpc_subscription_autoreconnect_tasks;
+pub mod grpcmultiplex_dedup;
+pub mod grpcmultiplex_demotion;
 pub mod grpcmultiplex_fastestwins;
+pub mod grpcmultiplex_forkaware;
+pub mod grpcmultiplex_quorum;
+pub mod grpcmultiplex_sanity;
+pub mod grpcmultiplex_stats;
 pub mod histogram_percentiles;
+pub mod latency_tracker;
 mod obfuscate;
+#[cfg(feature = "recording")]
+pub mod recording;
+#[cfg(feature = "recording")]
+pub mod replay;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod yellowstone_extractors;
 pub mod yellowstone_grpc_util;
 
 pub use yellowstone_grpc_proto::{convert_from, convert_to, geyser as yellowstone_proto};
@@ -56,6 +76,10 @@ pub struct GrpcSourceConfig {
     tls_config: Option<ClientTlsConfig>,
     timeouts: Option<GrpcConnectionTimeouts>,
     compression: Option<CompressionEncoding>,
+    // test only: faults applied to the subscription stream
+    fault_injector: Option<FaultInjector>,
+    // task API only; the stream API always sends them
+    connecting_messages: bool,
 }
 
 impl Display for GrpcSourceConfig {
@@ -94,6 +118,8 @@ impl GrpcSourceConfig {
             tls_config: None,
             timeouts: None,
             compression: None,
+            fault_injector: None,
+            connecting_messages: false,
         }
     }
     pub const fn new(
@@ -108,6 +134,8 @@ impl GrpcSourceConfig {
             tls_config,
             timeouts: Some(timeouts),
             compression: None,
+            fault_injector: None,
+            connecting_messages: false,
         }
     }
     pub const fn new_compressed(
@@ -122,8 +150,23 @@ impl GrpcSourceConfig {
             tls_config,
             timeouts: Some(timeouts),
             compression: Some(CompressionEncoding::Zstd),
+            fault_injector: None,
+            connecting_messages: false,
         }
     }
+
+    /// inject latency, drops, resets etc. between the grpc stream and the autoconnect task or stream; see ``FaultInjector``
+    pub fn with_fault_injector(mut self, fault_injector: FaultInjector) -> Self {
+        self.fault_injector = Some(fault_injector);
+        self
+    }
+
+    /// the autoconnect task sends ``Message::Connecting`` before every (re)connect attempt like the stream API does;
+    /// note: the task then waits for the downstream to pick up the message before connecting
+    pub const fn with_connecting_messages(mut self) -> Self {
+        self.connecting_messages = true;
+        self
+    }
 }
 
 #[derive(Clone)]
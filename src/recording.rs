@@ -0,0 +1,799 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{debug, info, warn};
+use prost::Message as _;
+use serde::{Deserialize, Serialize};
+use solana_clock::Slot;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use yellowstone_grpc_proto::geyser::SubscribeUpdate;
+
+use crate::yellowstone_extractors::update_slot;
+use crate::Message;
+
+const SEGMENT_PREFIX: &str = "segment-";
+const INDEX_FILE: &str = "index.jsonl";
+
+/// one recorded ``Message``; segments contain a sequence of length-delimited protobuf encoded records
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RecordedMessage {
+    // receive time in microseconds since unix epoch
+    #[prost(uint64, tag = "1")]
+    pub received_at_us: u64,
+    #[prost(uint32, tag = "2")]
+    pub source_id: u32,
+    // set for ``Message::Connecting``
+    #[prost(uint32, optional, tag = "3")]
+    pub connecting_attempt: Option<u32>,
+    // set for ``Message::GeyserSubscribeUpdate``
+    #[prost(message, optional, tag = "4")]
+    pub update: Option<SubscribeUpdate>,
+}
+
+impl RecordedMessage {
+    fn new(source_id: u32, message: &Message, received_at: SystemTime) -> Self {
+        match message {
+            Message::GeyserSubscribeUpdate(update) => RecordedMessage {
+                received_at_us: unix_micros(received_at),
+                source_id,
+                connecting_attempt: None,
+                update: Some(update.as_ref().clone()),
+            },
+            Message::Connecting(attempt) => RecordedMessage {
+                received_at_us: unix_micros(received_at),
+                source_id,
+                connecting_attempt: Some(*attempt),
+                update: None,
+            },
+        }
+    }
+
+    pub fn received_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(self.received_at_us)
+    }
+
+    pub fn slot(&self) -> Option<Slot> {
+        self.update
+            .as_ref()
+            .and_then(|update| update.update_oneof.as_ref())
+            .and_then(update_slot)
+    }
+
+    pub fn to_message(&self) -> Option<Message> {
+        match (&self.update, self.connecting_attempt) {
+            (Some(update), _) => Some(Message::GeyserSubscribeUpdate(Box::new(update.clone()))),
+            (None, Some(attempt)) => Some(Message::Connecting(attempt)),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SegmentCompression {
+    None,
+    Lz4,
+    Zstd { level: i32 },
+}
+
+impl SegmentCompression {
+    const fn extension(self) -> &'static str {
+        match self {
+            SegmentCompression::None => "bin",
+            SegmentCompression::Lz4 => "bin.lz4",
+            SegmentCompression::Zstd { .. } => "bin.zst",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Retention {
+    Unlimited,
+    // delete the oldest segments if the recording exceeds this size on disk
+    MaxTotalBytes(u64),
+    // delete segments whose last record is older
+    MaxAge(Duration),
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordingConfig {
+    pub directory: PathBuf,
+    pub compression: SegmentCompression,
+    // start a new segment if the uncompressed size of the current segment exceeds this
+    pub max_segment_bytes: u64,
+    // start a new segment if the current segment is older
+    pub max_segment_duration: Duration,
+    pub retention: Retention,
+}
+
+impl RecordingConfig {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        RecordingConfig {
+            directory: directory.into(),
+            compression: SegmentCompression::Lz4,
+            max_segment_bytes: 256 * 1024 * 1024,
+            max_segment_duration: Duration::from_secs(600),
+            retention: Retention::Unlimited,
+        }
+    }
+}
+
+/// entry of the slot index; written when a segment is closed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SegmentInfo {
+    pub file_name: String,
+    pub records: u64,
+    // size on disk
+    pub bytes: u64,
+    pub min_slot: Option<Slot>,
+    pub max_slot: Option<Slot>,
+    pub first_received_at_us: u64,
+    pub last_received_at_us: u64,
+}
+
+impl SegmentInfo {
+    fn overlaps(&self, slot_range: &RangeInclusive<Slot>) -> bool {
+        match (self.min_slot, self.max_slot) {
+            (Some(min_slot), Some(max_slot)) => {
+                min_slot <= *slot_range.end() && max_slot >= *slot_range.start()
+            }
+            // no slots at all (e.g. only pings) - nothing to replay from this segment
+            _ => false,
+        }
+    }
+}
+
+enum SegmentWriter {
+    Plain(BufWriter<File>),
+    Lz4(lz4_flex::frame::FrameEncoder<BufWriter<File>>),
+    Zstd(zstd::stream::write::Encoder<'static, BufWriter<File>>),
+}
+
+impl SegmentWriter {
+    fn create(path: &Path, compression: SegmentCompression) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match compression {
+            SegmentCompression::None => SegmentWriter::Plain(file),
+            SegmentCompression::Lz4 => SegmentWriter::Lz4(lz4_flex::frame::FrameEncoder::new(file)),
+            SegmentCompression::Zstd { level } => {
+                SegmentWriter::Zstd(zstd::stream::write::Encoder::new(file, level)?)
+            }
+        })
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            SegmentWriter::Plain(writer) => writer.write_all(buf),
+            SegmentWriter::Lz4(writer) => writer.write_all(buf),
+            SegmentWriter::Zstd(writer) => writer.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            SegmentWriter::Plain(writer) => writer.flush(),
+            SegmentWriter::Lz4(writer) => writer.flush(),
+            SegmentWriter::Zstd(writer) => writer.flush(),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            SegmentWriter::Plain(writer) => writer,
+            SegmentWriter::Lz4(writer) => writer.finish().map_err(io::Error::other)?,
+            SegmentWriter::Zstd(writer) => writer.finish()?,
+        };
+        file.flush()
+    }
+}
+
+struct ActiveSegment {
+    writer: SegmentWriter,
+    path: PathBuf,
+    info: SegmentInfo,
+    // uncompressed
+    bytes_written: u64,
+    opened_at: SystemTime,
+}
+
+/// writes every ``Message`` with receive timestamp and source id into rotating segment files; cheap to clone
+///
+/// note: writes are buffered but blocking; ``spawn_recording_tap`` writes on a blocking thread
+#[derive(Clone)]
+pub struct RecordingSink {
+    inner: Arc<Mutex<RecordingSinkInner>>,
+}
+
+struct RecordingSinkInner {
+    config: RecordingConfig,
+    next_segment_seq: u64,
+    active: Option<ActiveSegment>,
+    // closed segments, oldest first
+    segments: Vec<SegmentInfo>,
+    buf: Vec<u8>,
+}
+
+impl RecordingSink {
+    /// continues an existing recording in the directory
+    pub fn create(config: RecordingConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        let segments = read_index(&config.directory)?;
+        let next_segment_seq = list_segment_files(&config.directory)?
+            .iter()
+            .filter_map(|path| segment_seq(path))
+            .max()
+            .map_or(0, |seq| seq + 1);
+
+        Ok(RecordingSink {
+            inner: Arc::new(Mutex::new(RecordingSinkInner {
+                config,
+                next_segment_seq,
+                active: None,
+                segments,
+                buf: Vec::with_capacity(4096),
+            })),
+        })
+    }
+
+    pub fn record(&self, source_id: u32, message: &Message) -> io::Result<()> {
+        self.record_at(source_id, message, SystemTime::now())
+    }
+
+    pub fn record_at(
+        &self,
+        source_id: u32,
+        message: &Message,
+        received_at: SystemTime,
+    ) -> io::Result<()> {
+        self.write(&RecordedMessage::new(source_id, message, received_at))
+    }
+
+    fn write(&self, record: &RecordedMessage) -> io::Result<()> {
+        self.inner
+            .lock()
+            .expect("recording lock poisoned")
+            .write_record(record, record.received_at())
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        match &mut self.inner.lock().expect("recording lock poisoned").active {
+            Some(active) => active.writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// close the current segment and add it to the index; the next record starts a new segment
+    pub fn close_segment(&self) -> io::Result<()> {
+        self.inner
+            .lock()
+            .expect("recording lock poisoned")
+            .close_segment(SystemTime::now())
+    }
+
+    /// closed segments, oldest first
+    pub fn segments(&self) -> Vec<SegmentInfo> {
+        self.inner
+            .lock()
+            .expect("recording lock poisoned")
+            .segments
+            .clone()
+    }
+}
+
+impl RecordingSinkInner {
+    fn write_record(&mut self, record: &RecordedMessage, now: SystemTime) -> io::Result<()> {
+        if let Some(active) = &self.active {
+            let segment_age = now
+                .duration_since(active.opened_at)
+                .unwrap_or(Duration::ZERO);
+            if active.bytes_written >= self.config.max_segment_bytes
+                || segment_age >= self.config.max_segment_duration
+            {
+                self.close_segment(now)?;
+            }
+        }
+
+        if self.active.is_none() {
+            let file_name = format!(
+                "{}{:08}.{}",
+                SEGMENT_PREFIX,
+                self.next_segment_seq,
+                self.config.compression.extension()
+            );
+            self.next_segment_seq += 1;
+            let path = self.config.directory.join(&file_name);
+            debug!("start recording segment {}", path.display());
+            self.active = Some(ActiveSegment {
+                writer: SegmentWriter::create(&path, self.config.compression)?,
+                path,
+                info: SegmentInfo {
+                    file_name,
+                    records: 0,
+                    bytes: 0,
+                    min_slot: None,
+                    max_slot: None,
+                    first_received_at_us: record.received_at_us,
+                    last_received_at_us: record.received_at_us,
+                },
+                bytes_written: 0,
+                opened_at: now,
+            });
+        }
+        let active = self.active.as_mut().expect("segment was opened");
+
+        self.buf.clear();
+        record
+            .encode_length_delimited(&mut self.buf)
+            .expect("buffer has sufficient capacity");
+        active.writer.write_all(&self.buf)?;
+        active.bytes_written += self.buf.len() as u64;
+
+        let info = &mut active.info;
+        info.records += 1;
+        info.last_received_at_us = record.received_at_us;
+        if let Some(slot) = record.slot() {
+            info.min_slot = Some(info.min_slot.map_or(slot, |min_slot| min_slot.min(slot)));
+            info.max_slot = Some(info.max_slot.map_or(slot, |max_slot| max_slot.max(slot)));
+        }
+        Ok(())
+    }
+
+    fn close_segment(&mut self, now: SystemTime) -> io::Result<()> {
+        let Some(active) = self.active.take() else {
+            return Ok(());
+        };
+        active.writer.finish()?;
+        let mut info = active.info;
+        info.bytes = fs::metadata(&active.path)?.len();
+        debug!(
+            "closed recording segment {}: records={}, slots={:?}..={:?}",
+            info.file_name, info.records, info.min_slot, info.max_slot
+        );
+
+        let mut index = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.config.directory.join(INDEX_FILE))?;
+        writeln!(index, "{}", serde_json::to_string(&info)?)?;
+        self.segments.push(info);
+
+        self.apply_retention(now)
+    }
+
+    fn apply_retention(&mut self, now: SystemTime) -> io::Result<()> {
+        let num_expired = match self.config.retention {
+            Retention::Unlimited => 0,
+            Retention::MaxTotalBytes(max_total_bytes) => {
+                let mut total_bytes: u64 = self.segments.iter().map(|info| info.bytes).sum();
+                self.segments
+                    .iter()
+                    .take_while(|info| {
+                        let expired = total_bytes > max_total_bytes;
+                        total_bytes -= info.bytes;
+                        expired
+                    })
+                    .count()
+            }
+            Retention::MaxAge(max_age) => {
+                let oldest_us = unix_micros(now.checked_sub(max_age).unwrap_or(UNIX_EPOCH));
+                self.segments
+                    .iter()
+                    .take_while(|info| info.last_received_at_us < oldest_us)
+                    .count()
+            }
+        };
+        if num_expired == 0 {
+            return Ok(());
+        }
+
+        for info in self.segments.drain(..num_expired) {
+            info!("retention: delete recording segment {}", info.file_name);
+            if let Err(err) = fs::remove_file(self.config.directory.join(&info.file_name)) {
+                warn!(
+                    "failed to delete recording segment {}: {}",
+                    info.file_name, err
+                );
+            }
+        }
+
+        // rewrite the index atomically
+        let index_tmp = self.config.directory.join(format!("{}.tmp", INDEX_FILE));
+        let mut index = BufWriter::new(File::create(&index_tmp)?);
+        for info in &self.segments {
+            writeln!(index, "{}", serde_json::to_string(info)?)?;
+        }
+        index.flush()?;
+        drop(index);
+        fs::rename(index_tmp, self.config.directory.join(INDEX_FILE))
+    }
+}
+
+impl Drop for RecordingSinkInner {
+    fn drop(&mut self) {
+        if let Err(err) = self.close_segment(SystemTime::now()) {
+            warn!("failed to close recording segment: {}", err);
+        }
+    }
+}
+
+/// records all messages from upstream under the source id and forwards them to the returned receiver;
+/// recording errors are logged and do not interrupt the stream
+pub fn spawn_recording_tap(
+    recording_sink: RecordingSink,
+    source_id: u32,
+    mut upstream: mpsc::Receiver<Message>,
+    mut exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, mpsc::Receiver<Message>) {
+    let (downstream_tx, downstream_rx) = mpsc::channel::<Message>(1);
+
+    // keep the blocking file IO off the runtime workers
+    let (record_tx, mut record_rx) = mpsc::channel::<RecordedMessage>(1024);
+    let jh_writer = tokio::task::spawn_blocking(move || {
+        while let Some(record) = record_rx.blocking_recv() {
+            if let Err(err) = recording_sink.write(&record) {
+                warn!(
+                    "failed to record message from source {}: {}",
+                    record.source_id, err
+                );
+            }
+        }
+        if let Err(err) = recording_sink.flush() {
+            warn!("failed to flush recording: {}", err);
+        }
+    });
+
+    let jh_tap = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                next = upstream.recv() => {
+                    let Some(message) = next else {
+                        debug!("upstream closed - shutting down recording tap {}", source_id);
+                        break;
+                    };
+                    let record = RecordedMessage::new(source_id, &message, SystemTime::now());
+                    if record_tx.send(record).await.is_err() {
+                        warn!("recording writer stopped - not recording source {}", source_id);
+                    }
+                    if downstream_tx.send(message).await.is_err() {
+                        debug!("downstream closed - shutting down recording tap {}", source_id);
+                        break;
+                    }
+                }
+                _ = exit_notify.recv() => {
+                    debug!("exit on signal - shutting down recording tap {}", source_id);
+                    break;
+                }
+            }
+        }
+        // the writer flushes after the remaining records
+        drop(record_tx);
+        if let Err(err) = jh_writer.await {
+            warn!("recording writer failed: {}", err);
+        }
+    });
+
+    (jh_tap, downstream_rx)
+}
+
+/// segment files of the recording in the order they were written; with a slot range only segments
+/// which may contain slots from the range (according to the index) are returned
+///
+/// note: segments which were not closed properly are not in the index and always included
+pub fn list_segments(
+    directory: &Path,
+    slot_range: Option<&RangeInclusive<Slot>>,
+) -> io::Result<Vec<PathBuf>> {
+    let index = read_index(directory)?;
+    let segment_files = list_segment_files(directory)?;
+    Ok(segment_files
+        .into_iter()
+        .filter(|path| {
+            let Some(slot_range) = slot_range else {
+                return true;
+            };
+            let file_name = path.file_name().and_then(|name| name.to_str());
+            match index
+                .iter()
+                .find(|info| Some(info.file_name.as_str()) == file_name)
+            {
+                Some(info) => info.overlaps(slot_range),
+                None => true,
+            }
+        })
+        .collect())
+}
+
+/// iterates over the records of one segment file
+pub struct SegmentReader {
+    reader: Box<dyn Read + Send>,
+    buf: Vec<u8>,
+    // stop after the first error
+    failed: bool,
+}
+
+impl SegmentReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        let file_name = path.to_string_lossy();
+        let reader: Box<dyn Read + Send> = if file_name.ends_with(".lz4") {
+            Box::new(lz4_flex::frame::FrameDecoder::new(file))
+        } else if file_name.ends_with(".zst") {
+            Box::new(zstd::stream::read::Decoder::with_buffer(file)?)
+        } else {
+            Box::new(file)
+        };
+        Ok(SegmentReader {
+            reader,
+            buf: Vec::new(),
+            failed: false,
+        })
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<RecordedMessage>> {
+        let Some(len) = read_varint(&mut self.reader)? else {
+            return Ok(None);
+        };
+        self.buf.resize(len as usize, 0);
+        self.reader.read_exact(&mut self.buf)?;
+        let record = RecordedMessage::decode(self.buf.as_slice())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(Some(record))
+    }
+}
+
+impl Iterator for SegmentReader {
+    type Item = io::Result<RecordedMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = self.read_record().transpose();
+        self.failed = matches!(next, Some(Err(_)));
+        next
+    }
+}
+
+// None on clean end of file
+fn read_varint(reader: &mut impl Read) -> io::Result<Option<u64>> {
+    let mut value: u64 = 0;
+    for i in 0..10 {
+        let mut byte = [0u8; 1];
+        if reader.read(&mut byte)? == 0 {
+            if i == 0 {
+                return Ok(None);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        value |= ((byte[0] & 0x7f) as u64) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "invalid length delimiter",
+    ))
+}
+
+fn read_index(directory: &Path) -> io::Result<Vec<SegmentInfo>> {
+    let content = match fs::read_to_string(directory.join(INDEX_FILE)) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+    content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+        .collect()
+}
+
+fn list_segment_files(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut segment_files = vec![];
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if segment_seq(&path).is_some() {
+            segment_files.push(path);
+        }
+    }
+    // zero-padded sequence number
+    segment_files.sort();
+    Ok(segment_files)
+}
+
+fn segment_seq(path: &Path) -> Option<u64> {
+    let file_name = path.file_name()?.to_str()?;
+    let (seq, _extension) = file_name.strip_prefix(SEGMENT_PREFIX)?.split_once('.')?;
+    seq.parse().ok()
+}
+
+fn unix_micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_message, mock_ping_update, mock_slot_update};
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("geyser-recording-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn read_all(
+        directory: &Path,
+        slot_range: Option<&RangeInclusive<Slot>>,
+    ) -> Vec<RecordedMessage> {
+        list_segments(directory, slot_range)
+            .unwrap()
+            .iter()
+            .flat_map(|path| SegmentReader::open(path).unwrap())
+            .map(|record| record.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_roundtrip_with_rotation() {
+        for compression in [
+            SegmentCompression::None,
+            SegmentCompression::Lz4,
+            SegmentCompression::Zstd { level: 3 },
+        ] {
+            let directory = test_directory(compression.extension());
+            let sink = RecordingSink::create(RecordingConfig {
+                compression,
+                max_segment_bytes: 1,
+                ..RecordingConfig::new(&directory)
+            })
+            .unwrap();
+
+            let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+            sink.record_at(0, &Message::Connecting(1), start).unwrap();
+            for slot in 100..110 {
+                let received_at = start + Duration::from_millis(slot);
+                sink.record_at(
+                    (slot % 2) as u32,
+                    &mock_message(mock_slot_update(slot)),
+                    received_at,
+                )
+                .unwrap();
+            }
+            sink.close_segment().unwrap();
+            // one record per segment
+            assert_eq!(sink.segments().len(), 11);
+
+            let records = read_all(&directory, None);
+            assert_eq!(records.len(), 11);
+            assert!(matches!(
+                records[0].to_message(),
+                Some(Message::Connecting(1))
+            ));
+            assert_eq!(records[1].slot(), Some(100));
+            assert_eq!(records[1].source_id, 0);
+            assert_eq!(records[2].source_id, 1);
+            assert_eq!(
+                records[10].received_at(),
+                start + Duration::from_millis(109)
+            );
+
+            // slot index
+            let records = read_all(&directory, Some(&(105..=106)));
+            assert_eq!(
+                records
+                    .iter()
+                    .map(|record| record.slot())
+                    .collect::<Vec<_>>(),
+                vec![Some(105), Some(106)]
+            );
+
+            // continue the recording
+            drop(sink);
+            let sink = RecordingSink::create(RecordingConfig::new(&directory)).unwrap();
+            assert_eq!(sink.segments().len(), 11);
+            sink.record(0, &mock_message(mock_slot_update(110)))
+                .unwrap();
+            drop(sink);
+            assert_eq!(read_all(&directory, None).len(), 12);
+
+            fs::remove_dir_all(&directory).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_size_retention() {
+        let directory = test_directory("retention");
+        let sink = RecordingSink::create(RecordingConfig {
+            compression: SegmentCompression::None,
+            max_segment_bytes: 1,
+            retention: Retention::MaxTotalBytes(100),
+            ..RecordingConfig::new(&directory)
+        })
+        .unwrap();
+
+        let ping = mock_message(mock_ping_update());
+        for _ in 0..100 {
+            sink.record(0, &ping).unwrap();
+        }
+        sink.close_segment().unwrap();
+
+        let segments = sink.segments();
+        let total_bytes: u64 = segments.iter().map(|info| info.bytes).sum();
+        assert!(total_bytes <= 100);
+        assert!(segments.len() < 100);
+        assert_eq!(
+            list_segments(&directory, None).unwrap().len(),
+            segments.len()
+        );
+        assert_eq!(read_index(&directory).unwrap(), segments);
+
+        drop(sink);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_age_retention() {
+        let directory = test_directory("age-retention");
+        let sink = RecordingSink::create(RecordingConfig {
+            max_segment_duration: Duration::from_secs(60),
+            retention: Retention::MaxAge(Duration::from_secs(3600)),
+            ..RecordingConfig::new(&directory)
+        })
+        .unwrap();
+
+        // one segment per record; the age is relative to the record time, not the wall clock
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for i in 0..6 {
+            let received_at = start + Duration::from_secs(i * 1800);
+            sink.record_at(0, &mock_message(mock_slot_update(100 + i)), received_at)
+                .unwrap();
+        }
+        assert_eq!(
+            sink.segments()
+                .iter()
+                .map(|info| info.min_slot)
+                .collect::<Vec<_>>(),
+            vec![Some(103), Some(104)]
+        );
+
+        drop(sink);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recording_tap() {
+        let directory = test_directory("tap");
+        let sink = RecordingSink::create(RecordingConfig::new(&directory)).unwrap();
+        let (upstream_tx, upstream_rx) = mpsc::channel(16);
+        let (_exit_tx, exit_rx) = broadcast::channel(1);
+        let (jh_tap, mut downstream_rx) =
+            spawn_recording_tap(sink.clone(), 3, upstream_rx, exit_rx);
+
+        for slot in 100..110 {
+            upstream_tx
+                .send(mock_message(mock_slot_update(slot)))
+                .await
+                .unwrap();
+            assert!(downstream_rx.recv().await.is_some());
+        }
+        drop(upstream_tx);
+        jh_tap.await.unwrap();
+        sink.close_segment().unwrap();
+
+        let records = read_all(&directory, None);
+        assert_eq!(records.len(), 10);
+        assert!(records.iter().all(|record| record.source_id == 3));
+
+        drop(sink);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
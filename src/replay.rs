@@ -0,0 +1,446 @@
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::time::{Duration, SystemTime};
+
+use async_stream::stream;
+use futures::{Stream, StreamExt};
+use log::{debug, info, warn};
+use solana_clock::Slot;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, sleep_until, Instant};
+
+use crate::recording::{list_segments, RecordedMessage, SegmentReader};
+use crate::{Attempt, Message};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayPacing {
+    // keep the intervals between the receive timestamps
+    Original,
+    // intervals divided by the factor; a factor which is not positive replays as fast as possible,
+    // factors below MIN_ACCELERATION_FACTOR are clamped
+    Accelerated(f64),
+    AsFastAsPossible,
+}
+
+/// simulate a connection loss of the replayed source
+#[derive(Clone, Debug)]
+pub struct SyntheticDisconnect {
+    // disconnect after this number of replayed messages
+    pub after_messages: u64,
+    // recorded messages which are lost while disconnected
+    pub lost_messages: u64,
+    // time until reconnect; not affected by pacing
+    pub downtime: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplayConfig {
+    // directory of a recording created by ``RecordingSink``
+    pub directory: PathBuf,
+    pub pacing: ReplayPacing,
+    // skip updates outside the slot range; messages without slot are always replayed
+    pub slot_range: Option<RangeInclusive<Slot>>,
+    // replay only the messages recorded for this source id
+    pub source_id: Option<u32>,
+    pub disconnects: Vec<SyntheticDisconnect>,
+}
+
+impl ReplayConfig {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        ReplayConfig {
+            directory: directory.into(),
+            pacing: ReplayPacing::AsFastAsPossible,
+            slot_range: None,
+            source_id: None,
+            disconnects: vec![],
+        }
+    }
+}
+
+// slow down a replay at most a million times
+pub const MIN_ACCELERATION_FACTOR: f64 = 1e-6;
+// deadline if the replay timeline overflows; same as tokio's far future
+const FAR_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
+
+/// maps the receive timestamps of the recording to the replay timeline
+struct Pacer {
+    pacing: ReplayPacing,
+    // (first receive timestamp, replay start)
+    origin: Option<(SystemTime, Instant)>,
+}
+
+impl Pacer {
+    const fn new(pacing: ReplayPacing) -> Self {
+        Pacer {
+            pacing,
+            origin: None,
+        }
+    }
+
+    fn deadline(&mut self, received_at: SystemTime, now: Instant) -> Option<Instant> {
+        let factor = match self.pacing {
+            ReplayPacing::Original => 1.0,
+            ReplayPacing::Accelerated(factor) if factor > 0.0 => {
+                factor.max(MIN_ACCELERATION_FACTOR)
+            }
+            // includes NaN
+            ReplayPacing::Accelerated(_) | ReplayPacing::AsFastAsPossible => return None,
+        };
+        let (recorded_start, replay_start) = *self.origin.get_or_insert((received_at, now));
+        let recorded_elapsed = received_at
+            .duration_since(recorded_start)
+            .unwrap_or(Duration::ZERO);
+        let replay_elapsed = Duration::try_from_secs_f64(recorded_elapsed.as_secs_f64() / factor)
+            .unwrap_or(FAR_FUTURE);
+        Some(
+            replay_start
+                .checked_add(replay_elapsed)
+                .unwrap_or_else(|| now + FAR_FUTURE),
+        )
+    }
+
+    // shift the timeline by the time spent disconnected
+    fn delay(&mut self, downtime: Duration) {
+        if let Some((_, replay_start)) = &mut self.origin {
+            *replay_start += downtime;
+        }
+    }
+}
+
+/// replay a recording as stream of ``Message``s; the stream ends with the recording
+///
+/// see ``create_geyser_reconnecting_stream``
+pub fn create_replay_stream(replay_config: ReplayConfig) -> impl Stream<Item = Message> {
+    stream! {
+        let segments = match list_segments(&replay_config.directory, replay_config.slot_range.as_ref()) {
+            Ok(segments) => segments,
+            Err(err) => {
+                warn!("failed to list recording segments in {}: {}", replay_config.directory.display(), err);
+                return;
+            }
+        };
+        info!("replay {} segments from {}", segments.len(), replay_config.directory.display());
+
+        let mut records = spawn_segment_reader(segments);
+        let mut pacer = Pacer::new(replay_config.pacing);
+        let mut disconnects = replay_config.disconnects.clone();
+        disconnects.sort_by_key(|disconnect| disconnect.after_messages);
+        let mut disconnects = disconnects.into_iter().peekable();
+        let mut replayed: u64 = 0;
+        let mut lost: u64 = 0;
+        let mut attempt: Attempt = 0;
+
+        while let Some(record) = records.recv().await {
+            let record = match record {
+                Ok(record) => record,
+                Err(err) => {
+                    warn!("failed to read recording - stop replay: {}", err);
+                    break;
+                }
+            };
+            if !is_selected(&record, &replay_config) {
+                continue;
+            }
+            // synthetic disconnects continue the numbering of the recorded attempts
+            if let Some(recorded_attempt) = record.connecting_attempt {
+                attempt = recorded_attempt;
+            }
+            if lost > 0 {
+                lost -= 1;
+                continue;
+            }
+            let Some(message) = record.to_message() else {
+                continue;
+            };
+
+            if let Some(deadline) = pacer.deadline(record.received_at(), Instant::now()) {
+                sleep_until(deadline).await;
+            }
+            yield message;
+            replayed += 1;
+
+            if let Some(disconnect) = disconnects.next_if(|disconnect| disconnect.after_messages <= replayed) {
+                attempt += 1;
+                debug!("synthetic disconnect after {} messages for {:?}", replayed, disconnect.downtime);
+                sleep(disconnect.downtime).await;
+                pacer.delay(disconnect.downtime);
+                lost = disconnect.lost_messages;
+                yield Message::Connecting(attempt);
+            }
+        }
+        debug!("replay finished after {} messages", replayed);
+    }
+}
+
+/// replay a recording into the mpsc channel like ``create_geyser_autoconnection_task_with_mpsc``;
+/// the task ends with the recording, on exit signal or when the receiver is dropped
+pub fn create_replay_task_with_mpsc(
+    replay_config: ReplayConfig,
+    mpsc_downstream: mpsc::Sender<Message>,
+    mut exit_notify: broadcast::Receiver<()>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut replay_stream = pin!(create_replay_stream(replay_config));
+        loop {
+            tokio::select! {
+                next = replay_stream.next() => {
+                    let Some(message) = next else {
+                        break;
+                    };
+                    if mpsc_downstream.send(message).await.is_err() {
+                        debug!("downstream receiver closed - stop replay");
+                        break;
+                    }
+                }
+                _ = exit_notify.recv() => {
+                    debug!("exit on signal - stop replay");
+                    break;
+                }
+            }
+        }
+    })
+}
+
+pub fn create_replay_task(
+    replay_config: ReplayConfig,
+    exit_notify: broadcast::Receiver<()>,
+) -> (JoinHandle<()>, mpsc::Receiver<Message>) {
+    let (sender, receiver_channel) = mpsc::channel::<Message>(1);
+    let join_handle = create_replay_task_with_mpsc(replay_config, sender, exit_notify);
+    (join_handle, receiver_channel)
+}
+
+fn is_selected(record: &RecordedMessage, replay_config: &ReplayConfig) -> bool {
+    if replay_config
+        .source_id
+        .is_some_and(|source_id| source_id != record.source_id)
+    {
+        return false;
+    }
+    match (&replay_config.slot_range, record.slot()) {
+        (Some(slot_range), Some(slot)) => slot_range.contains(&slot),
+        _ => true,
+    }
+}
+
+// file io and decompression run on a blocking thread
+fn spawn_segment_reader(segments: Vec<PathBuf>) -> mpsc::Receiver<io::Result<RecordedMessage>> {
+    let (records_tx, records_rx) = mpsc::channel(1024);
+    tokio::task::spawn_blocking(move || {
+        for path in segments {
+            let reader = match SegmentReader::open(&path) {
+                Ok(reader) => reader,
+                Err(err) => {
+                    let _ = records_tx.blocking_send(Err(err));
+                    return;
+                }
+            };
+            for record in reader {
+                if records_tx.blocking_send(record).is_err() {
+                    // replay was stopped
+                    return;
+                }
+            }
+        }
+    });
+    records_rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::{RecordingConfig, RecordingSink, SegmentCompression};
+    use crate::testing::{mock_message, mock_slot_update};
+    use std::fs;
+    use std::time::UNIX_EPOCH;
+    use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+
+    fn replayed_slots(messages: &[Message]) -> Vec<Option<Slot>> {
+        messages
+            .iter()
+            .map(|message| match message {
+                Message::GeyserSubscribeUpdate(update) => match update.update_oneof {
+                    Some(UpdateOneof::Slot(ref slot)) => Some(slot.slot),
+                    _ => None,
+                },
+                Message::Connecting(_) => None,
+            })
+            .collect()
+    }
+
+    // slots 100..120 from source 0 and 1000..1020 from source 1, 10ms apart
+    fn create_recording(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("geyser-replay-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let sink = RecordingSink::create(RecordingConfig {
+            compression: SegmentCompression::Lz4,
+            max_segment_bytes: 64,
+            ..RecordingConfig::new(&directory)
+        })
+        .unwrap();
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for i in 0..20 {
+            let received_at = start + Duration::from_millis(i * 10);
+            sink.record_at(0, &mock_message(mock_slot_update(100 + i)), received_at)
+                .unwrap();
+            sink.record_at(1, &mock_message(mock_slot_update(1000 + i)), received_at)
+                .unwrap();
+        }
+        sink.close_segment().unwrap();
+        directory
+    }
+
+    #[tokio::test]
+    async fn test_replay_slot_range_and_disconnect() {
+        let directory = create_recording("range");
+        let (exit_tx, _) = broadcast::channel(1);
+        let (jh_replay, mut replay_rx) = create_replay_task(
+            ReplayConfig {
+                slot_range: Some(105..=114),
+                source_id: Some(0),
+                disconnects: vec![SyntheticDisconnect {
+                    after_messages: 3,
+                    lost_messages: 2,
+                    downtime: Duration::from_millis(10),
+                }],
+                ..ReplayConfig::new(&directory)
+            },
+            exit_tx.subscribe(),
+        );
+
+        let mut messages = vec![];
+        while let Some(message) = replay_rx.recv().await {
+            messages.push(message);
+        }
+        jh_replay.await.unwrap();
+
+        assert!(matches!(messages[3], Message::Connecting(1)));
+        assert_eq!(
+            replayed_slots(&messages),
+            vec![
+                Some(105),
+                Some(106),
+                Some(107),
+                None,
+                Some(110),
+                Some(111),
+                Some(112),
+                Some(113),
+                Some(114)
+            ]
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_pacing() {
+        let directory = create_recording("pacing");
+        let replay_config = ReplayConfig {
+            source_id: Some(1),
+            pacing: ReplayPacing::Original,
+            ..ReplayConfig::new(&directory)
+        };
+
+        let started_at = Instant::now();
+        let messages: Vec<Message> = create_replay_stream(replay_config.clone()).collect().await;
+        assert_eq!(messages.len(), 20);
+        // 190ms between first and last message
+        let original_elapsed = started_at.elapsed();
+        assert!(original_elapsed >= Duration::from_millis(190));
+
+        let started_at = Instant::now();
+        let messages: Vec<Message> = create_replay_stream(ReplayConfig {
+            pacing: ReplayPacing::Accelerated(10.0),
+            ..replay_config
+        })
+        .collect()
+        .await;
+        assert_eq!(replayed_slots(&messages)[19], Some(1019));
+        assert!(started_at.elapsed() >= Duration::from_millis(19));
+        assert!(started_at.elapsed() < original_elapsed);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_pacer_invalid_factor() {
+        let now = Instant::now();
+        let received_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for factor in [0.0, -2.0, f64::NAN] {
+            let mut pacer = Pacer::new(ReplayPacing::Accelerated(factor));
+            assert_eq!(pacer.deadline(received_at, now), None);
+            assert_eq!(
+                pacer.deadline(received_at + Duration::from_secs(1), now),
+                None
+            );
+        }
+
+        let mut pacer = Pacer::new(ReplayPacing::Accelerated(4.0));
+        assert_eq!(pacer.deadline(received_at, now), Some(now));
+        assert_eq!(
+            pacer.deadline(received_at + Duration::from_secs(1), now),
+            Some(now + Duration::from_millis(250))
+        );
+    }
+
+    #[test]
+    fn test_pacer_tiny_factor() {
+        let now = Instant::now();
+        let received_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut pacer = Pacer::new(ReplayPacing::Accelerated(1e-20));
+        assert_eq!(pacer.deadline(received_at, now), Some(now));
+        // clamped to the minimum factor
+        assert_eq!(
+            pacer.deadline(received_at + Duration::from_secs(1), now),
+            Some(now + Duration::from_secs(1_000_000))
+        );
+        // neither the replay duration nor the deadline overflows
+        for recorded_secs in [10_000_000_000_000, u64::MAX / 4] {
+            let deadline = pacer
+                .deadline(received_at + Duration::from_secs(recorded_secs), now)
+                .unwrap();
+            assert!(deadline >= now + Duration::from_secs(86400 * 365));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_continues_recorded_attempts() {
+        let directory =
+            std::env::temp_dir().join(format!("geyser-replay-attempts-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let sink = RecordingSink::create(RecordingConfig::new(&directory)).unwrap();
+        sink.record(0, &Message::Connecting(1)).unwrap();
+        sink.record(0, &Message::Connecting(2)).unwrap();
+        for slot in 100..103 {
+            sink.record(0, &mock_message(mock_slot_update(slot)))
+                .unwrap();
+        }
+        sink.close_segment().unwrap();
+
+        let messages: Vec<Message> = create_replay_stream(ReplayConfig {
+            disconnects: vec![SyntheticDisconnect {
+                after_messages: 3,
+                lost_messages: 0,
+                downtime: Duration::ZERO,
+            }],
+            ..ReplayConfig::new(&directory)
+        })
+        .collect()
+        .await;
+        let attempts: Vec<Attempt> = messages
+            .iter()
+            .filter_map(|message| match message {
+                Message::Connecting(attempt) => Some(*attempt),
+                Message::GeyserSubscribeUpdate(_) => None,
+            })
+            .collect();
+        assert_eq!(attempts, vec![1, 2, 3]);
+
+        drop(sink);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,587 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_stream::stream;
+use log::{debug, warn};
+use solana_clock::Slot;
+use solana_pubkey::Pubkey;
+use tokio::net::TcpListener;
+use tokio::select;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+use yellowstone_grpc_proto::geyser::geyser_server::{Geyser, GeyserServer};
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{
+    GetBlockHeightRequest, GetBlockHeightResponse, GetLatestBlockhashRequest,
+    GetLatestBlockhashResponse, GetSlotRequest, GetSlotResponse, GetVersionRequest,
+    GetVersionResponse, IsBlockhashValidRequest, IsBlockhashValidResponse, PingRequest,
+    PongResponse, SlotStatus, SubscribeReplayInfoRequest, SubscribeReplayInfoResponse,
+    SubscribeRequest, SubscribeUpdate, SubscribeUpdateAccount, SubscribeUpdateAccountInfo,
+    SubscribeUpdateBlockMeta, SubscribeUpdatePing, SubscribeUpdateSlot,
+};
+
+#[cfg(test)]
+use crate::grpcmultiplex_fastestwins::FromYellowstoneExtractor;
+use crate::{GrpcConnectionTimeouts, GrpcSourceConfig, Message};
+
+/// step of a scripted subscription
+#[derive(Clone, Debug)]
+pub enum MockAction {
+    // send the update to the client
+    Update(SubscribeUpdate),
+    Delay(Duration),
+    // terminate the stream with the status
+    Fail(Status),
+    // end the stream without error
+    Close,
+    // keep the stream open without sending anything
+    StopResponding,
+}
+
+/// how the mock handles filter updates sent on an open subscription
+#[derive(Clone, Debug, Default)]
+pub enum MockFilterUpdates {
+    #[default]
+    Accept,
+    // do not read from the request stream after the initial filter
+    Ignore,
+    // terminate the stream with the status like the real server does for invalid filters
+    Reject(Status),
+}
+
+/// script for one call of ``Subscribe``; the stream stays open after the last action
+#[derive(Clone, Debug, Default)]
+pub struct MockSubscription {
+    // wait before responding to the subscribe call
+    pub subscribe_delay: Option<Duration>,
+    // respond to the subscribe call with the status instead of opening a stream
+    pub reject_subscribe: Option<Status>,
+    pub actions: Vec<MockAction>,
+    pub filter_updates: MockFilterUpdates,
+}
+
+impl MockSubscription {
+    pub fn new() -> Self {
+        MockSubscription::default()
+    }
+
+    pub fn emit(mut self, update: SubscribeUpdate) -> Self {
+        self.actions.push(MockAction::Update(update));
+        self
+    }
+
+    pub fn emit_all(mut self, updates: impl IntoIterator<Item = SubscribeUpdate>) -> Self {
+        self.actions
+            .extend(updates.into_iter().map(MockAction::Update));
+        self
+    }
+
+    pub fn delay(mut self, duration: Duration) -> Self {
+        self.actions.push(MockAction::Delay(duration));
+        self
+    }
+
+    pub fn fail(mut self, status: Status) -> Self {
+        self.actions.push(MockAction::Fail(status));
+        self
+    }
+
+    pub fn close(mut self) -> Self {
+        self.actions.push(MockAction::Close);
+        self
+    }
+
+    pub fn stop_responding(mut self) -> Self {
+        self.actions.push(MockAction::StopResponding);
+        self
+    }
+
+    pub const fn delay_subscribe(mut self, duration: Duration) -> Self {
+        self.subscribe_delay = Some(duration);
+        self
+    }
+
+    pub fn reject_subscribe(mut self, status: Status) -> Self {
+        self.reject_subscribe = Some(status);
+        self
+    }
+
+    pub fn ignore_filter_updates(mut self) -> Self {
+        self.filter_updates = MockFilterUpdates::Ignore;
+        self
+    }
+
+    pub fn reject_filter_updates(mut self, status: Status) -> Self {
+        self.filter_updates = MockFilterUpdates::Reject(status);
+        self
+    }
+}
+
+struct MockState {
+    // consumed in order by subscribe calls
+    subscriptions: Mutex<VecDeque<MockSubscription>>,
+    // initial filters and filter updates in the order they were received
+    received_requests: Mutex<Vec<SubscribeRequest>>,
+    subscribe_calls: AtomicUsize,
+    shutdown_rx: watch::Receiver<bool>,
+}
+
+/// in-process Yellowstone gRPC server on localhost which plays scripted subscriptions
+///
+/// every call of ``Subscribe`` consumes the next ``MockSubscription``; if there is none left the call is
+/// rejected with ``Status::unavailable``; the server shuts down when dropped
+pub struct MockGeyserServer {
+    local_addr: SocketAddr,
+    state: Arc<MockState>,
+    shutdown_tx: watch::Sender<bool>,
+    jh_server: JoinHandle<()>,
+}
+
+impl MockGeyserServer {
+    pub async fn start(
+        subscriptions: impl IntoIterator<Item = MockSubscription>,
+    ) -> io::Result<MockGeyserServer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let local_addr = listener.local_addr()?;
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let state = Arc::new(MockState {
+            subscriptions: Mutex::new(subscriptions.into_iter().collect()),
+            received_requests: Mutex::new(vec![]),
+            subscribe_calls: AtomicUsize::new(0),
+            shutdown_rx: shutdown_rx.clone(),
+        });
+
+        let incoming = stream! {
+            loop {
+                yield listener.accept().await.map(|(tcp_stream, _)| tcp_stream);
+            }
+        };
+        let geyser = MockGeyser {
+            state: state.clone(),
+        };
+        let mut server_shutdown_rx = shutdown_rx;
+        let jh_server = tokio::spawn(async move {
+            let serve_result = Server::builder()
+                .add_service(GeyserServer::new(geyser))
+                .serve_with_incoming_shutdown(incoming, async move {
+                    let _ = server_shutdown_rx.changed().await;
+                })
+                .await;
+            if let Err(err) = serve_result {
+                warn!("mock geyser server failed: {}", err);
+            }
+        });
+        debug!("mock geyser server listening on {}", local_addr);
+
+        Ok(MockGeyserServer {
+            local_addr,
+            state,
+            shutdown_tx,
+            jh_server,
+        })
+    }
+
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn grpc_addr(&self) -> String {
+        format!("http://{}", self.local_addr)
+    }
+
+    pub fn grpc_source(&self, timeouts: GrpcConnectionTimeouts) -> GrpcSourceConfig {
+        GrpcSourceConfig::new(self.grpc_addr(), None, None, timeouts)
+    }
+
+    /// script the next subscription
+    pub fn push_subscription(&self, subscription: MockSubscription) {
+        self.state
+            .subscriptions
+            .lock()
+            .expect("mock state lock poisoned")
+            .push_back(subscription);
+    }
+
+    pub fn subscribe_calls(&self) -> usize {
+        self.state.subscribe_calls.load(Ordering::Relaxed)
+    }
+
+    /// initial filters and filter updates received on all subscriptions
+    pub fn received_requests(&self) -> Vec<SubscribeRequest> {
+        self.state
+            .received_requests
+            .lock()
+            .expect("mock state lock poisoned")
+            .clone()
+    }
+
+    /// close all streams and stop the server
+    pub async fn shutdown(mut self) {
+        let _ = self.shutdown_tx.send(true);
+        let _ = (&mut self.jh_server).await;
+    }
+}
+
+impl Drop for MockGeyserServer {
+    fn drop(&mut self) {
+        let _ = self.shutdown_tx.send(true);
+    }
+}
+
+pub fn mock_slot_update(slot: u64) -> SubscribeUpdate {
+    SubscribeUpdate {
+        filters: vec!["mock".to_string()],
+        update_oneof: Some(UpdateOneof::Slot(SubscribeUpdateSlot {
+            slot,
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+pub fn mock_slot_status_update(slot: Slot, status: SlotStatus) -> SubscribeUpdate {
+    SubscribeUpdate {
+        filters: vec!["mock".to_string()],
+        update_oneof: Some(UpdateOneof::Slot(SubscribeUpdateSlot {
+            slot,
+            status: status as i32,
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+pub fn mock_block_meta_update(slot: Slot, parent_slot: Slot) -> SubscribeUpdate {
+    SubscribeUpdate {
+        filters: vec!["mock".to_string()],
+        update_oneof: Some(UpdateOneof::BlockMeta(SubscribeUpdateBlockMeta {
+            slot,
+            parent_slot,
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+pub fn mock_account_update(
+    pubkey: Pubkey,
+    owner: Pubkey,
+    slot: Slot,
+    write_version: u64,
+    lamports: u64,
+    data: Vec<u8>,
+) -> SubscribeUpdate {
+    SubscribeUpdate {
+        filters: vec!["mock".to_string()],
+        update_oneof: Some(UpdateOneof::Account(SubscribeUpdateAccount {
+            account: Some(SubscribeUpdateAccountInfo {
+                pubkey: pubkey.to_bytes().to_vec(),
+                owner: owner.to_bytes().to_vec(),
+                lamports,
+                data,
+                write_version,
+                ..Default::default()
+            }),
+            slot,
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+/// wrap the update as received from an autoconnect task
+pub fn mock_message(update: SubscribeUpdate) -> Message {
+    Message::GeyserSubscribeUpdate(Box::new(update))
+}
+
+/// extracts the slot of block meta updates; use with ``mock_block_meta_update``
+#[cfg(test)]
+pub(crate) struct BlockMetaSlotExtractor;
+
+#[cfg(test)]
+impl FromYellowstoneExtractor for BlockMetaSlotExtractor {
+    type Target = Slot;
+    fn map_yellowstone_update(&self, update: SubscribeUpdate) -> Option<(Slot, Slot)> {
+        match update.update_oneof {
+            Some(UpdateOneof::BlockMeta(meta)) => Some((meta.slot, meta.slot)),
+            _ => None,
+        }
+    }
+}
+
+pub fn mock_ping_update() -> SubscribeUpdate {
+    SubscribeUpdate {
+        filters: vec!["mock".to_string()],
+        update_oneof: Some(UpdateOneof::Ping(SubscribeUpdatePing {})),
+        ..Default::default()
+    }
+}
+
+struct MockGeyser {
+    state: Arc<MockState>,
+}
+
+type UpdateSender = mpsc::Sender<Result<SubscribeUpdate, Status>>;
+
+/// signals the end of one scripted subscription; also fires on server shutdown
+#[derive(Clone)]
+struct StopSignal {
+    stop_tx: Arc<watch::Sender<bool>>,
+    stop_rx: watch::Receiver<bool>,
+    shutdown_rx: watch::Receiver<bool>,
+}
+
+impl StopSignal {
+    fn stop(&self) {
+        let _ = self.stop_tx.send(true);
+    }
+
+    async fn stopped(&mut self) {
+        if *self.stop_rx.borrow() || *self.shutdown_rx.borrow() {
+            return;
+        }
+        select! {
+            _ = self.stop_rx.changed() => {}
+            _ = self.shutdown_rx.changed() => {}
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Geyser for MockGeyser {
+    type SubscribeStream = ReceiverStream<Result<SubscribeUpdate, Status>>;
+
+    async fn subscribe(
+        &self,
+        request: Request<Streaming<SubscribeRequest>>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let call = self.state.subscribe_calls.fetch_add(1, Ordering::Relaxed) + 1;
+        let subscription = self
+            .state
+            .subscriptions
+            .lock()
+            .expect("mock state lock poisoned")
+            .pop_front();
+        let Some(subscription) = subscription else {
+            debug!("mock subscribe call #{} - no subscription scripted", call);
+            return Err(Status::unavailable("no subscription scripted"));
+        };
+        debug!("mock subscribe call #{}: {:?}", call, subscription.actions);
+
+        if let Some(subscribe_delay) = subscription.subscribe_delay {
+            sleep(subscribe_delay).await;
+        }
+        if let Some(status) = subscription.reject_subscribe {
+            return Err(status);
+        }
+
+        let (updates_tx, updates_rx) = mpsc::channel(16);
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let stop_signal = StopSignal {
+            stop_tx: Arc::new(stop_tx),
+            stop_rx,
+            shutdown_rx: self.state.shutdown_rx.clone(),
+        };
+
+        tokio::spawn(handle_filter_updates(
+            request.into_inner(),
+            subscription.filter_updates,
+            self.state.clone(),
+            updates_tx.clone(),
+            stop_signal.clone(),
+        ));
+        tokio::spawn(run_actions(subscription.actions, updates_tx, stop_signal));
+
+        Ok(Response::new(ReceiverStream::new(updates_rx)))
+    }
+
+    async fn subscribe_replay_info(
+        &self,
+        _request: Request<SubscribeReplayInfoRequest>,
+    ) -> Result<Response<SubscribeReplayInfoResponse>, Status> {
+        Err(Status::unimplemented("not supported by mock"))
+    }
+
+    async fn ping(&self, request: Request<PingRequest>) -> Result<Response<PongResponse>, Status> {
+        Ok(Response::new(PongResponse {
+            count: request.into_inner().count,
+        }))
+    }
+
+    async fn get_latest_blockhash(
+        &self,
+        _request: Request<GetLatestBlockhashRequest>,
+    ) -> Result<Response<GetLatestBlockhashResponse>, Status> {
+        Err(Status::unimplemented("not supported by mock"))
+    }
+
+    async fn get_block_height(
+        &self,
+        _request: Request<GetBlockHeightRequest>,
+    ) -> Result<Response<GetBlockHeightResponse>, Status> {
+        Err(Status::unimplemented("not supported by mock"))
+    }
+
+    async fn get_slot(
+        &self,
+        _request: Request<GetSlotRequest>,
+    ) -> Result<Response<GetSlotResponse>, Status> {
+        Err(Status::unimplemented("not supported by mock"))
+    }
+
+    async fn is_blockhash_valid(
+        &self,
+        _request: Request<IsBlockhashValidRequest>,
+    ) -> Result<Response<IsBlockhashValidResponse>, Status> {
+        Err(Status::unimplemented("not supported by mock"))
+    }
+
+    async fn get_version(
+        &self,
+        _request: Request<GetVersionRequest>,
+    ) -> Result<Response<GetVersionResponse>, Status> {
+        Ok(Response::new(GetVersionResponse {
+            version: "mock".to_string(),
+        }))
+    }
+}
+
+async fn run_actions(
+    actions: Vec<MockAction>,
+    updates_tx: UpdateSender,
+    mut stop_signal: StopSignal,
+) {
+    for action in actions {
+        match action {
+            MockAction::Update(update) => {
+                select! {
+                    send_result = updates_tx.send(Ok(update)) => {
+                        if send_result.is_err() {
+                            debug!("mock client disconnected");
+                            return;
+                        }
+                    }
+                    _ = stop_signal.stopped() => return,
+                }
+            }
+            MockAction::Delay(duration) => {
+                select! {
+                    _ = sleep(duration) => {}
+                    _ = stop_signal.stopped() => return,
+                }
+            }
+            MockAction::Fail(status) => {
+                let _ = updates_tx.send(Err(status)).await;
+                stop_signal.stop();
+                return;
+            }
+            MockAction::Close => {
+                stop_signal.stop();
+                return;
+            }
+            MockAction::StopResponding => break,
+        }
+    }
+
+    // keep the stream open until the client disconnects
+    select! {
+        _ = updates_tx.closed() => {}
+        _ = stop_signal.stopped() => {}
+    }
+}
+
+async fn handle_filter_updates(
+    mut incoming: Streaming<SubscribeRequest>,
+    filter_updates: MockFilterUpdates,
+    state: Arc<MockState>,
+    updates_tx: UpdateSender,
+    mut stop_signal: StopSignal,
+) {
+    let mut is_initial_filter = true;
+    let mut reading = true;
+    loop {
+        let next_request = select! {
+            next_request = incoming.message(), if reading => next_request,
+            _ = stop_signal.stopped() => break,
+        };
+        let request = match next_request {
+            Ok(Some(request)) => request,
+            Ok(None) | Err(_) => {
+                // client closed the request stream; the response stream stays open
+                reading = false;
+                continue;
+            }
+        };
+
+        if !is_initial_filter {
+            if let MockFilterUpdates::Reject(status) = &filter_updates {
+                debug!("mock rejects filter update with {:?}", status.code());
+                let _ = updates_tx.send(Err(status.clone())).await;
+                stop_signal.stop();
+                break;
+            }
+        }
+        state
+            .received_requests
+            .lock()
+            .expect("mock state lock poisoned")
+            .push(request);
+        if matches!(filter_updates, MockFilterUpdates::Ignore) {
+            reading = false;
+        }
+        is_initial_filter = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yellowstone_grpc_util::connect_with_timeout;
+    use futures::StreamExt;
+    use tonic::Code;
+    use yellowstone_grpc_client::GeyserGrpcClientError;
+
+    #[tokio::test]
+    async fn test_scripted_subscription() {
+        let server = MockGeyserServer::start([MockSubscription::new()
+            .emit(mock_slot_update(42))
+            .fail(Status::internal("scripted failure"))])
+        .await
+        .unwrap();
+
+        let mut client =
+            connect_with_timeout(server.grpc_addr(), None::<String>, None, None, None, None)
+                .await
+                .unwrap();
+        let (_subscribe_tx, mut geyser_stream) = client
+            .subscribe_with_request(Some(SubscribeRequest::default()))
+            .await
+            .unwrap();
+
+        let update = geyser_stream.next().await.unwrap().unwrap();
+        assert_eq!(update.update_oneof, mock_slot_update(42).update_oneof);
+        let status = geyser_stream.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::Internal);
+
+        // no more subscriptions scripted
+        let Err(GeyserGrpcClientError::TonicStatus(status)) = client
+            .subscribe_with_request(Some(SubscribeRequest::default()))
+            .await
+        else {
+            panic!("subscribe must be rejected");
+        };
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(server.subscribe_calls(), 2);
+
+        server.shutdown().await;
+    }
+}
@@ -0,0 +1,551 @@
+use std::str::FromStr;
+
+use log::warn;
+use solana_clock::Slot;
+use solana_commitment_config::CommitmentConfig;
+use solana_compute_budget_interface::ComputeBudgetInstruction;
+use solana_hash::Hash;
+use solana_message::VersionedMessage;
+use solana_pubkey::Pubkey;
+use solana_signature::Signature;
+use solana_transaction::TransactionError;
+use yellowstone_grpc_proto::convert_from;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{
+    SlotStatus, SubscribeUpdate, SubscribeUpdateAccount, SubscribeUpdateBlock,
+    SubscribeUpdateBlockMeta, SubscribeUpdateSlot, SubscribeUpdateTransactionInfo,
+};
+
+use crate::grpcmultiplex_fastestwins::{FromYellowstoneExtractor, MultiplexExtractor};
+
+#[derive(Default, Debug, Clone)]
+pub struct ProducedBlock {
+    pub transactions: Vec<TransactionInfo>,
+    pub blockhash: Hash,
+    pub block_height: Option<u64>,
+    pub slot: Slot,
+    pub parent_slot: Slot,
+    pub block_time: Option<i64>,
+    pub commitment_config: CommitmentConfig,
+    pub previous_blockhash: Hash,
+}
+
+#[derive(Debug, Clone)]
+pub struct TransactionInfo {
+    pub signature: Signature,
+    pub slot: Slot,
+    // position in the block
+    pub index: u64,
+    pub is_vote: bool,
+    pub err: Option<TransactionError>,
+    pub fee: u64,
+    pub cu_requested: Option<u32>,
+    pub prioritization_fees: Option<u64>,
+    pub cu_consumed: Option<u64>,
+    pub recent_blockhash: Hash,
+    pub message: VersionedMessage,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockMeta {
+    pub slot: Slot,
+    pub parent_slot: Slot,
+    pub blockhash: Hash,
+    pub parent_blockhash: Hash,
+    pub block_height: Option<u64>,
+    pub block_time: Option<i64>,
+    pub executed_transaction_count: u64,
+    pub commitment_config: CommitmentConfig,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotUpdate {
+    pub slot: Slot,
+    pub parent: Option<Slot>,
+    pub status: SlotStatus,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    pub data: Vec<u8>,
+    pub write_version: u64,
+    pub txn_signature: Option<Signature>,
+    pub slot: Slot,
+    pub is_startup: bool,
+}
+
+/// full blocks incl. transactions; use with ``GeyserFilter::blocks_and_txs``
+pub struct BlockExtractor(pub CommitmentConfig);
+
+impl FromYellowstoneExtractor for BlockExtractor {
+    type Target = ProducedBlock;
+    fn map_yellowstone_update(&self, update: SubscribeUpdate) -> Option<(Slot, Self::Target)> {
+        match update.update_oneof {
+            Some(UpdateOneof::Block(update_block_message)) => {
+                let block = map_produced_block(update_block_message, self.0)?;
+                Some((block.slot, block))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// every transaction of a block as separate item ordered by (slot, index); use with ``GeyserFilter::blocks_and_txs``
+pub struct BlockTransactionsExtractor;
+
+impl MultiplexExtractor for BlockTransactionsExtractor {
+    type Key = (Slot, u64);
+    type Target = TransactionInfo;
+    type Items = Vec<((Slot, u64), TransactionInfo)>;
+    fn extract(&self, _source_idx: usize, update: SubscribeUpdate) -> Self::Items {
+        match update.update_oneof {
+            Some(UpdateOneof::Block(update_block_message)) => {
+                let slot = update_block_message.slot;
+                update_block_message
+                    .transactions
+                    .into_iter()
+                    .filter_map(|tx| map_transaction_info(slot, tx))
+                    .map(|tx| ((slot, tx.index), tx))
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// use with ``GeyserFilter::blocks_meta``
+pub struct BlockMetaExtractor(pub CommitmentConfig);
+
+impl FromYellowstoneExtractor for BlockMetaExtractor {
+    type Target = BlockMeta;
+    fn map_yellowstone_update(&self, update: SubscribeUpdate) -> Option<(Slot, Self::Target)> {
+        match update.update_oneof {
+            Some(UpdateOneof::BlockMeta(update_blockmeta_message)) => {
+                let block_meta = map_block_meta(update_blockmeta_message, self.0)?;
+                Some((block_meta.slot, block_meta))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// use with ``GeyserFilter::slots``; only the first status update per slot passes the multiplexer
+pub struct SlotExtractor;
+
+impl FromYellowstoneExtractor for SlotExtractor {
+    type Target = SlotUpdate;
+    fn map_yellowstone_update(&self, update: SubscribeUpdate) -> Option<(Slot, Self::Target)> {
+        match update.update_oneof {
+            Some(UpdateOneof::Slot(update_slot_message)) => {
+                let slot_update = map_slot_update(update_slot_message)?;
+                Some((slot_update.slot, slot_update))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// account updates ordered by (slot, write_version); use with ``GeyserFilter::accounts``
+///
+/// CAUTION: the write version is assigned by the validator, so this is only meaningful for sources backed by the same node;
+/// use ``create_deduplicated_stream`` to multiplex independent sources
+pub struct AccountExtractor;
+
+impl MultiplexExtractor for AccountExtractor {
+    type Key = (Slot, u64);
+    type Target = AccountUpdate;
+    type Items = Option<((Slot, u64), AccountUpdate)>;
+    fn extract(&self, _source_idx: usize, update: SubscribeUpdate) -> Self::Items {
+        match update.update_oneof {
+            Some(UpdateOneof::Account(update_account_message)) => {
+                let account = map_account_update(update_account_message)?;
+                Some(((account.slot, account.write_version), account))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// transactions ordered by (slot, index); use with a transactions filter
+///
+/// CAUTION: geyser does not stream the transactions of a slot in index order and the multiplexer drops keys behind the
+/// highest emitted key, so a transaction arriving after a higher index of the same slot (from any source) is dropped;
+/// use ``create_deduplicated_stream`` to receive all transactions
+pub struct TransactionExtractor;
+
+impl MultiplexExtractor for TransactionExtractor {
+    type Key = (Slot, u64);
+    type Target = TransactionInfo;
+    type Items = Option<((Slot, u64), TransactionInfo)>;
+    fn extract(&self, _source_idx: usize, update: SubscribeUpdate) -> Self::Items {
+        match update.update_oneof {
+            Some(UpdateOneof::Transaction(update_transaction_message)) => {
+                let tx = map_transaction_info(
+                    update_transaction_message.slot,
+                    update_transaction_message.transaction?,
+                )?;
+                Some(((tx.slot, tx.index), tx))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// slot of the update; None for ping and pong
+pub const fn update_slot(update_oneof: &UpdateOneof) -> Option<Slot> {
+    match update_oneof {
+        UpdateOneof::Account(account) => Some(account.slot),
+        UpdateOneof::Slot(slot) => Some(slot.slot),
+        UpdateOneof::Transaction(transaction) => Some(transaction.slot),
+        UpdateOneof::TransactionStatus(transaction_status) => Some(transaction_status.slot),
+        UpdateOneof::Block(block) => Some(block.slot),
+        UpdateOneof::BlockMeta(block_meta) => Some(block_meta.slot),
+        UpdateOneof::Entry(entry) => Some(entry.slot),
+        UpdateOneof::Ping(_) | UpdateOneof::Pong(_) => None,
+    }
+}
+
+/// returns None if the block hashes cannot be parsed; transactions which cannot be decoded are skipped
+pub fn map_produced_block(
+    block: SubscribeUpdateBlock,
+    commitment_config: CommitmentConfig,
+) -> Option<ProducedBlock> {
+    let slot = block.slot;
+    let transactions = block
+        .transactions
+        .into_iter()
+        .filter_map(|tx| map_transaction_info(slot, tx))
+        .collect();
+
+    Some(ProducedBlock {
+        transactions,
+        blockhash: parse_hash(&block.blockhash)?,
+        block_height: block
+            .block_height
+            .map(|block_height| block_height.block_height),
+        slot,
+        parent_slot: block.parent_slot,
+        block_time: block.block_time.map(|time| time.timestamp),
+        commitment_config,
+        previous_blockhash: parse_hash(&block.parent_blockhash)?,
+    })
+}
+
+pub fn map_transaction_info(
+    slot: Slot,
+    tx: SubscribeUpdateTransactionInfo,
+) -> Option<TransactionInfo> {
+    let signature = Signature::try_from(tx.signature.as_slice()).ok()?;
+    let meta = tx.meta?;
+    let message = tx.transaction?.message?;
+
+    let message = match convert_from::create_message(message) {
+        Ok(message) => message,
+        Err(err) => {
+            warn!(
+                "skip transaction {} with invalid message: {:?}",
+                signature, err
+            );
+            return None;
+        }
+    };
+    let err = match convert_from::create_tx_error(meta.err.as_ref()) {
+        Ok(err) => err,
+        Err(err) => {
+            warn!(
+                "skip transaction {} with invalid error: {:?}",
+                signature, err
+            );
+            return None;
+        }
+    };
+
+    let cu_requested = compute_budget_instructions(&message).find_map(|ix| match ix {
+        ComputeBudgetInstruction::SetComputeUnitLimit(limit) => Some(limit),
+        _ => None,
+    });
+    let prioritization_fees = compute_budget_instructions(&message).find_map(|ix| match ix {
+        ComputeBudgetInstruction::SetComputeUnitPrice(price) => Some(price),
+        _ => None,
+    });
+
+    Some(TransactionInfo {
+        signature,
+        slot,
+        index: tx.index,
+        is_vote: tx.is_vote,
+        err,
+        fee: meta.fee,
+        cu_requested,
+        prioritization_fees,
+        cu_consumed: meta.compute_units_consumed,
+        recent_blockhash: *message.recent_blockhash(),
+        message,
+    })
+}
+
+pub fn map_block_meta(
+    block_meta: SubscribeUpdateBlockMeta,
+    commitment_config: CommitmentConfig,
+) -> Option<BlockMeta> {
+    Some(BlockMeta {
+        slot: block_meta.slot,
+        parent_slot: block_meta.parent_slot,
+        blockhash: parse_hash(&block_meta.blockhash)?,
+        parent_blockhash: parse_hash(&block_meta.parent_blockhash)?,
+        block_height: block_meta
+            .block_height
+            .map(|block_height| block_height.block_height),
+        block_time: block_meta.block_time.map(|time| time.timestamp),
+        executed_transaction_count: block_meta.executed_transaction_count,
+        commitment_config,
+    })
+}
+
+pub fn map_slot_update(slot_update: SubscribeUpdateSlot) -> Option<SlotUpdate> {
+    Some(SlotUpdate {
+        slot: slot_update.slot,
+        parent: slot_update.parent,
+        status: SlotStatus::try_from(slot_update.status).ok()?,
+    })
+}
+
+pub fn map_account_update(account_update: SubscribeUpdateAccount) -> Option<AccountUpdate> {
+    let account = account_update.account?;
+    let txn_signature = match account.txn_signature {
+        Some(sig) => Some(Signature::try_from(sig.as_slice()).ok()?),
+        None => None,
+    };
+
+    Some(AccountUpdate {
+        pubkey: Pubkey::try_from(account.pubkey.as_slice()).ok()?,
+        owner: Pubkey::try_from(account.owner.as_slice()).ok()?,
+        lamports: account.lamports,
+        executable: account.executable,
+        rent_epoch: account.rent_epoch,
+        data: account.data,
+        write_version: account.write_version,
+        txn_signature,
+        slot: account_update.slot,
+        is_startup: account_update.is_startup,
+    })
+}
+
+fn parse_hash(hash: &str) -> Option<Hash> {
+    match Hash::from_str(hash) {
+        Ok(hash) => Some(hash),
+        Err(err) => {
+            warn!("invalid hash {}: {}", hash, err);
+            None
+        }
+    }
+}
+
+fn compute_budget_instructions(
+    message: &VersionedMessage,
+) -> impl Iterator<Item = ComputeBudgetInstruction> + '_ {
+    message.instructions().iter().filter_map(|ix| {
+        if ix
+            .program_id(message.static_account_keys())
+            .eq(&solana_sdk_ids::compute_budget::id())
+        {
+            solana_borsh::v1::try_from_slice_unchecked(ix.data.as_slice()).ok()
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_message::compiled_instruction::CompiledInstruction;
+    use solana_message::{v0, MessageHeader};
+    use yellowstone_grpc_proto::convert_to;
+    use yellowstone_grpc_proto::geyser::SubscribeUpdateAccountInfo;
+    use yellowstone_grpc_proto::prelude::{
+        BlockHeight, Transaction, TransactionStatusMeta, UnixTimestamp,
+    };
+
+    // sample values in the shape of a mainnet block update
+    const SAMPLE_SLOT: Slot = 369143450;
+    const SAMPLE_BLOCKHASH: Hash = Hash::new_from_array([11; 32]);
+    const SAMPLE_PARENT_BLOCKHASH: Hash = Hash::new_from_array([12; 32]);
+
+    fn sample_signature() -> Signature {
+        Signature::from([13; 64])
+    }
+
+    fn sample_message() -> VersionedMessage {
+        let payer = Pubkey::new_from_array([7; 32]);
+        let compute_budget_ix = |data: Vec<u8>| CompiledInstruction {
+            program_id_index: 1,
+            accounts: vec![],
+            data,
+        };
+        VersionedMessage::V0(v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: vec![payer, solana_sdk_ids::compute_budget::id()],
+            recent_blockhash: SAMPLE_PARENT_BLOCKHASH,
+            instructions: vec![
+                compute_budget_ix(ComputeBudgetInstruction::set_compute_unit_limit(200_000).data),
+                compute_budget_ix(ComputeBudgetInstruction::set_compute_unit_price(5_000).data),
+            ],
+            address_table_lookups: vec![],
+        })
+    }
+
+    fn sample_transaction(index: u64) -> SubscribeUpdateTransactionInfo {
+        let signature = sample_signature();
+        SubscribeUpdateTransactionInfo {
+            signature: signature.as_ref().to_vec(),
+            is_vote: false,
+            transaction: Some(Transaction {
+                signatures: vec![signature.as_ref().to_vec()],
+                message: Some(convert_to::create_message(&sample_message())),
+            }),
+            meta: Some(TransactionStatusMeta {
+                fee: 10_000,
+                compute_units_consumed: Some(42_000),
+                ..Default::default()
+            }),
+            index,
+            ..Default::default()
+        }
+    }
+
+    fn sample_block() -> SubscribeUpdateBlock {
+        SubscribeUpdateBlock {
+            slot: SAMPLE_SLOT,
+            blockhash: SAMPLE_BLOCKHASH.to_string(),
+            block_time: Some(UnixTimestamp {
+                timestamp: 1758793840,
+            }),
+            block_height: Some(BlockHeight {
+                block_height: 347412345,
+            }),
+            parent_slot: SAMPLE_SLOT - 1,
+            parent_blockhash: SAMPLE_PARENT_BLOCKHASH.to_string(),
+            executed_transaction_count: 2,
+            transactions: vec![sample_transaction(0), sample_transaction(1)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_block_extractor() {
+        let update = SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Block(sample_block())),
+            ..Default::default()
+        };
+
+        let (slot, block) = BlockExtractor(CommitmentConfig::confirmed())
+            .map_yellowstone_update(update)
+            .unwrap();
+
+        assert_eq!(slot, SAMPLE_SLOT);
+        assert_eq!(block.blockhash, SAMPLE_BLOCKHASH);
+        assert_eq!(block.previous_blockhash, SAMPLE_PARENT_BLOCKHASH);
+        assert_eq!(block.block_height, Some(347412345));
+        assert_eq!(block.transactions.len(), 2);
+
+        let tx = &block.transactions[1];
+        assert_eq!(tx.signature, sample_signature());
+        assert_eq!(tx.index, 1);
+        assert_eq!(tx.err, None);
+        assert_eq!(tx.cu_requested, Some(200_000));
+        assert_eq!(tx.prioritization_fees, Some(5_000));
+        assert_eq!(tx.cu_consumed, Some(42_000));
+        assert_eq!(tx.recent_blockhash, SAMPLE_PARENT_BLOCKHASH);
+        assert_eq!(tx.message, sample_message());
+    }
+
+    #[test]
+    fn test_block_transactions_extractor() {
+        let update = SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Block(sample_block())),
+            ..Default::default()
+        };
+
+        let keys = BlockTransactionsExtractor
+            .extract(0, update)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+
+        assert_eq!(keys, vec![(SAMPLE_SLOT, 0), (SAMPLE_SLOT, 1)]);
+    }
+
+    #[test]
+    fn test_block_meta_and_slot_extractor() {
+        let update = SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::BlockMeta(SubscribeUpdateBlockMeta {
+                slot: SAMPLE_SLOT,
+                blockhash: SAMPLE_BLOCKHASH.to_string(),
+                parent_slot: SAMPLE_SLOT - 1,
+                parent_blockhash: SAMPLE_PARENT_BLOCKHASH.to_string(),
+                executed_transaction_count: 1234,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let (slot, block_meta) = BlockMetaExtractor(CommitmentConfig::finalized())
+            .map_yellowstone_update(update)
+            .unwrap();
+        assert_eq!(slot, SAMPLE_SLOT);
+        assert_eq!(block_meta.parent_slot, SAMPLE_SLOT - 1);
+        assert_eq!(block_meta.executed_transaction_count, 1234);
+
+        let update = SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Slot(SubscribeUpdateSlot {
+                slot: SAMPLE_SLOT,
+                parent: Some(SAMPLE_SLOT - 1),
+                status: SlotStatus::SlotConfirmed as i32,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let (_, slot_update) = SlotExtractor.map_yellowstone_update(update).unwrap();
+        assert_eq!(slot_update.status, SlotStatus::SlotConfirmed);
+        assert_eq!(slot_update.parent, Some(SAMPLE_SLOT - 1));
+    }
+
+    #[test]
+    fn test_account_extractor() {
+        let pubkey = Pubkey::new_from_array([1; 32]);
+        let owner = Pubkey::new_from_array([2; 32]);
+        let update = SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Account(SubscribeUpdateAccount {
+                account: Some(SubscribeUpdateAccountInfo {
+                    pubkey: pubkey.to_bytes().to_vec(),
+                    lamports: 2039280,
+                    owner: owner.to_bytes().to_vec(),
+                    executable: false,
+                    rent_epoch: u64::MAX,
+                    data: vec![0; 165],
+                    write_version: 1_464_712_934_017,
+                    ..Default::default()
+                }),
+                slot: SAMPLE_SLOT,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        let ((slot, write_version), account) = AccountExtractor.extract(0, update).unwrap();
+        assert_eq!(slot, SAMPLE_SLOT);
+        assert_eq!(write_version, 1_464_712_934_017);
+        assert_eq!(account.pubkey, pubkey);
+        assert_eq!(account.owner, owner);
+        assert_eq!(account.data.len(), 165);
+    }
+}
@@ -1,16 +1,28 @@
+use std::net::SocketAddr;
 use std::time::Duration;
 use tonic::codec::CompressionEncoding;
 
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use itertools::Itertools;
+use log::{debug, warn};
+use tokio::net::lookup_host;
+use tokio::time::timeout;
+
 use tonic::metadata::errors::InvalidMetadataValue;
 use tonic::metadata::AsciiMetadataValue;
 use tonic::service::Interceptor;
-use tonic::transport::ClientTlsConfig;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
 use tonic_health::pb::health_client::HealthClient;
 use yellowstone_grpc_client::{GeyserGrpcBuilderResult, GeyserGrpcClient, InterceptorXToken};
 use yellowstone_grpc_proto::geyser::geyser_client::GeyserClient;
 use yellowstone_grpc_proto::geyser::SubscribeRequest;
 use yellowstone_grpc_proto::prost::bytes::Bytes;
 
+use url::{Host, Url};
+
+use crate::obfuscate::url_obfuscate_api_token;
+
 pub async fn connect_with_timeout<E, T>(
     endpoint: E,
     x_token: Option<T>,
@@ -85,8 +97,241 @@ where
     E: Into<Bytes>,
     T: TryInto<AsciiMetadataValue, Error = InvalidMetadataValue>,
 {
+    let endpoint = configure_endpoint(
+        Endpoint::from_shared(endpoint)?,
+        tls_config,
+        connect_timeout,
+        request_timeout,
+        &buffer_config,
+    )?;
+
+    let interceptor = create_interceptor(x_token)?;
+
+    let channel = endpoint.connect_lazy();
+
+    Ok(create_client(channel, interceptor, compression))
+}
+
+// see RFC 8305 "Connection Attempt Delay"
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+// bounds each connection attempt if no connect timeout is configured
+const DEFAULT_CONNECT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// resolve all A/AAAA records of the endpoint host and connect to the first address that accepts the connection;
+/// the hostname is re-resolved on every call, so call this on every reconnect
+///
+/// connection attempts are started happy-eyeballs style: the next address is tried if the previous attempt failed or
+/// did not succeed within 250ms; earlier attempts keep running and the first established connection wins
+///
+/// returns the address the client is connected to; falls back to lazy connect (and no address) if name resolution
+/// or all connection attempts fail
+pub async fn connect_with_failover<T>(
+    endpoint: String,
+    x_token: Option<T>,
+    tls_config: Option<ClientTlsConfig>,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    buffer_config: GeyserGrpcClientBufferConfig,
+    compression: Option<CompressionEncoding>,
+) -> GeyserGrpcBuilderResult<(GeyserGrpcClient<impl Interceptor>, Option<SocketAddr>)>
+where
+    T: TryInto<AsciiMetadataValue, Error = InvalidMetadataValue>,
+{
+    let interceptor = create_interceptor(x_token)?;
+    let origin = Endpoint::from_shared(endpoint.clone())?.uri().clone();
+
+    let resolved_addrs = resolve_endpoint_addrs(&endpoint).await;
+    if resolved_addrs.is_empty() {
+        debug!(
+            "no addresses resolved - connect lazily: endpoint={}",
+            url_obfuscate_api_token(&endpoint)
+        );
+        let channel = connect_lazily(
+            endpoint,
+            tls_config,
+            connect_timeout,
+            request_timeout,
+            &buffer_config,
+        )?;
+        return Ok((create_client(channel, interceptor, compression), None));
+    }
+    // cannot fail as the addresses were resolved from the parsed endpoint
+    let Ok(url) = Url::parse(&endpoint) else {
+        let channel = connect_lazily(
+            endpoint,
+            tls_config,
+            connect_timeout,
+            request_timeout,
+            &buffer_config,
+        )?;
+        return Ok((create_client(channel, interceptor, compression), None));
+    };
+
+    // certificate must be verified against the hostname, not the IP address
+    let tls_config = match (tls_config, url.host_str()) {
+        (Some(tls_config), Some(host)) => Some(tls_config.domain_name(host)),
+        (tls_config, _) => tls_config,
+    };
+
+    let mut addr_endpoints = Vec::with_capacity(resolved_addrs.len());
+    for addr in resolved_addrs {
+        let mut addr_url = url.clone();
+        if addr_url.set_ip_host(addr.ip()).is_err() || addr_url.set_port(Some(addr.port())).is_err()
+        {
+            continue;
+        }
+        let addr_endpoint = configure_endpoint(
+            Endpoint::from_shared(addr_url.to_string())?.origin(origin.clone()),
+            tls_config.clone(),
+            connect_timeout,
+            request_timeout,
+            &buffer_config,
+        )?;
+        addr_endpoints.push((addr, addr_endpoint));
+    }
+    let first_endpoint = addr_endpoints
+        .first()
+        .map(|(_, addr_endpoint)| addr_endpoint.clone());
+
+    let attempt_timeout = connect_timeout.unwrap_or(DEFAULT_CONNECT_ATTEMPT_TIMEOUT);
+    let mut remaining = addr_endpoints.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    loop {
+        if let Some((addr, addr_endpoint)) = remaining.next() {
+            attempts.push(connect_attempt(addr, addr_endpoint, attempt_timeout));
+        }
+
+        // start the next attempt early if none of the running attempts finishes in time
+        let finished_attempt = if remaining.as_slice().is_empty() {
+            attempts.next().await
+        } else {
+            match timeout(CONNECTION_ATTEMPT_DELAY, attempts.next()).await {
+                Ok(finished_attempt) => finished_attempt,
+                Err(_elapsed) => continue,
+            }
+        };
+
+        match finished_attempt {
+            Some((addr, Some(channel))) => {
+                debug!("connected: addr={}", addr);
+                let client = create_client(channel, interceptor, compression);
+                return Ok((client, Some(addr)));
+            }
+            // failed attempt; start the next one right away
+            Some((_addr, None)) => continue,
+            None => break,
+        }
+    }
+
+    // none of the addresses accepted the connection; hand out a lazy client for the first address
+    // - the error will surface on subscribe and trigger the regular reconnect cycle
+    // - no address is returned as the client is not connected
+    warn!(
+        "connect failed on all resolved addresses: endpoint={}",
+        url_obfuscate_api_token(&endpoint)
+    );
+    let channel = match first_endpoint {
+        Some(addr_endpoint) => addr_endpoint.connect_lazy(),
+        None => connect_lazily(
+            endpoint,
+            tls_config,
+            connect_timeout,
+            request_timeout,
+            &buffer_config,
+        )?,
+    };
+    Ok((create_client(channel, interceptor, compression), None))
+}
+
+fn connect_lazily(
+    endpoint: String,
+    tls_config: Option<ClientTlsConfig>,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    buffer_config: &GeyserGrpcClientBufferConfig,
+) -> GeyserGrpcBuilderResult<Channel> {
+    let endpoint = configure_endpoint(
+        Endpoint::from_shared(endpoint)?,
+        tls_config,
+        connect_timeout,
+        request_timeout,
+        buffer_config,
+    )?;
+    Ok(endpoint.connect_lazy())
+}
+
+async fn connect_attempt(
+    addr: SocketAddr,
+    endpoint: Endpoint,
+    attempt_timeout: Duration,
+) -> (SocketAddr, Option<Channel>) {
+    match timeout(attempt_timeout, endpoint.connect()).await {
+        Ok(Ok(channel)) => (addr, Some(channel)),
+        Ok(Err(connect_error)) => {
+            debug!(
+                "connect failed - trying next address: addr={}, error={:#}",
+                addr, connect_error
+            );
+            (addr, None)
+        }
+        Err(_elapsed) => {
+            debug!("connect timeout - trying next address: addr={}", addr);
+            (addr, None)
+        }
+    }
+}
+
+/// resolve all A/AAAA records of the endpoint host
+///
+/// addresses are ordered happy-eyeballs style: alternating address families starting with the family of the first record
+pub async fn resolve_endpoint_addrs(endpoint: &str) -> Vec<SocketAddr> {
+    let Ok(url) = Url::parse(endpoint) else {
+        return vec![];
+    };
+    let (Some(host), Some(port)) = (url.host(), url.port_or_known_default()) else {
+        return vec![];
+    };
+
+    let resolved: Vec<SocketAddr> = match host {
+        Host::Ipv4(ip) => vec![SocketAddr::new(ip.into(), port)],
+        Host::Ipv6(ip) => vec![SocketAddr::new(ip.into(), port)],
+        Host::Domain(domain) => match lookup_host((domain, port)).await {
+            Ok(addrs) => addrs.unique().collect(),
+            Err(resolve_error) => {
+                warn!(
+                    "failed to resolve host: host={}, error={:#}",
+                    domain, resolve_error
+                );
+                return vec![];
+            }
+        },
+    };
+
+    interleave_address_families(resolved)
+}
+
+fn interleave_address_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let prefer_ipv6 = addrs.first().is_some_and(|addr| addr.is_ipv6());
+    let (ipv6, ipv4): (Vec<SocketAddr>, Vec<SocketAddr>) =
+        addrs.into_iter().partition(|addr| addr.is_ipv6());
+    let (preferred, other) = if prefer_ipv6 {
+        (ipv6, ipv4)
+    } else {
+        (ipv4, ipv6)
+    };
+
+    preferred.into_iter().interleave(other).collect()
+}
+
+fn configure_endpoint(
+    endpoint: Endpoint,
+    tls_config: Option<ClientTlsConfig>,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    buffer_config: &GeyserGrpcClientBufferConfig,
+) -> GeyserGrpcBuilderResult<Endpoint> {
     // see https://github.com/blockworks-foundation/geyser-grpc-connector/issues/10
-    let mut endpoint = tonic::transport::Endpoint::from_shared(endpoint)?
+    let mut endpoint = endpoint
         .tcp_nodelay(true)
         .http2_adaptive_window(true)
         .buffer_size(buffer_config.buffer_size)
@@ -105,17 +350,28 @@ where
         endpoint = endpoint.timeout(request_timeout);
     }
 
+    Ok(endpoint)
+}
+
+fn create_interceptor<T>(x_token: Option<T>) -> GeyserGrpcBuilderResult<InterceptorXToken>
+where
+    T: TryInto<AsciiMetadataValue, Error = InvalidMetadataValue>,
+{
     let x_token: Option<AsciiMetadataValue> = match x_token {
         Some(x_token) => Some(x_token.try_into()?),
         None => None,
     };
-    let interceptor = InterceptorXToken {
+    Ok(InterceptorXToken {
         x_token,
         x_request_snapshot: false,
-    };
-
-    let channel = endpoint.connect_lazy();
+    })
+}
 
+fn create_client(
+    channel: Channel,
+    interceptor: InterceptorXToken,
+    compression: Option<CompressionEncoding>,
+) -> GeyserGrpcClient<InterceptorXToken> {
     let health_client = HealthClient::with_interceptor(channel.clone(), interceptor.clone());
 
     let geyser_client = GeyserClient::with_interceptor(channel.clone(), interceptor.clone())
@@ -126,6 +382,69 @@ where
         geyser_client
     };
 
-    let client = GeyserGrpcClient::new(health_client, geyser_client);
-    Ok(client)
+    GeyserGrpcClient::new(health_client, geyser_client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve_ip_literal() {
+        let addrs = resolve_endpoint_addrs("http://127.0.0.1:10000").await;
+        assert_eq!(addrs, vec!["127.0.0.1:10000".parse().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_default_port() {
+        let addrs = resolve_endpoint_addrs("https://[::1]/a991fba00fagbad").await;
+        assert_eq!(addrs, vec!["[::1]:443".parse().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_invalid() {
+        let addrs = resolve_endpoint_addrs("::::invalid").await;
+        assert!(addrs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_no_connected_addr_if_all_addresses_fail() {
+        // port is free after the listener is dropped
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let (_client, connected_addr) = connect_with_failover(
+            format!("http://127.0.0.1:{}", port),
+            None::<String>,
+            None,
+            Some(Duration::from_secs(1)),
+            None,
+            GeyserGrpcClientBufferConfig::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(connected_addr, None);
+    }
+
+    #[test]
+    fn test_interleave_address_families() {
+        let addrs: Vec<SocketAddr> = vec![
+            "10.0.0.1:443".parse().unwrap(),
+            "10.0.0.2:443".parse().unwrap(),
+            "10.0.0.3:443".parse().unwrap(),
+            "[2001:db8::1]:443".parse().unwrap(),
+        ];
+        let ordered = interleave_address_families(addrs);
+        assert_eq!(
+            ordered,
+            vec![
+                "10.0.0.1:443".parse::<SocketAddr>().unwrap(),
+                "[2001:db8::1]:443".parse().unwrap(),
+                "10.0.0.2:443".parse().unwrap(),
+                "10.0.0.3:443".parse().unwrap(),
+            ]
+        );
+    }
 }